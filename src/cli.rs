@@ -0,0 +1,500 @@
+use crate::definition::actions::Stage;
+use crate::definition::graph::RecipeGraph;
+use crate::definition::repository::{RecipeRepository, RECIPES_DIR};
+use crate::definition::rewrite::add_checksums;
+use crate::definition::{Artifact, ArtifactSource};
+use crate::engine::cache::{CacheEntry, CacheEntryKind};
+use crate::engine::config::EngineConfig;
+use crate::{Engine, Recipe};
+use anyhow::bail;
+use clap::{Args, Parser, Subcommand};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A small single binary build system
+#[derive(Debug, Parser)]
+#[command(name = "hob", version)]
+pub struct Cli {
+    /// Configuration file loaded after the system, user and project configuration
+    #[arg(short, long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Amount of recipes to build at the same time
+    #[arg(short, long, global = true)]
+    pub jobs: Option<usize>,
+
+    /// Never use the network, only artifacts that are already in the cache
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run every stage for the given recipes
+    Build(Targets),
+    /// Fetch the artifacts of the given recipes into the cache, use `--all` to prepare for offline builds
+    Fetch(Targets),
+    /// Fetch and extract the artifacts of the given recipes
+    Extract(Targets),
+    /// Package the already installed files of the given recipes
+    Package(Targets),
+    /// Show information about the given recipes
+    Info(Targets),
+    /// Fetch artifacts without a sha256 checksum and add it to their recipe file
+    Checksum(Targets),
+    /// Remove build directories of the given recipes, or all of them if none are given
+    Clean(Targets),
+    /// Inspect and clean up the artifact cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Serve the artifact cache over http, for other machines to use as `cache-server`
+    ServeCache {
+        /// Address to listen on
+        #[arg(short, long, value_name = "ADDR", default_value = "0.0.0.0:8085")]
+        listen: SocketAddr,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// List cached artifacts with the recipes they belong to
+    List(Targets),
+    /// Check every cached artifact against the recipes it belongs to
+    Verify(Targets),
+    /// Remove cached artifacts that none of the recipes in the repository refer to
+    Gc(GcArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct GcArgs {
+    #[command(flatten)]
+    pub targets: Targets,
+
+    /// Only remove unreferenced artifacts fetched more than this many days ago
+    #[arg(long, value_name = "DAYS")]
+    pub older_than: Option<u64>,
+
+    /// Only show what would be removed
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct Targets {
+    /// Recipe repository to load, defaults to the current directory if it contains `recipes/`
+    #[arg(short, long, value_name = "DIR")]
+    pub repo: Option<PathBuf>,
+
+    /// Recipe file to load, recipes inside can be selected by name
+    #[arg(short, long = "file", value_name = "FILE")]
+    pub files: Vec<PathBuf>,
+
+    /// Select every loaded recipe
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Also select the loaded recipes the given recipes depend on
+    #[arg(short, long)]
+    pub deps: bool,
+
+    /// Recipe files, or names of recipes or what they provide
+    #[arg(value_name = "FILE|RECIPE")]
+    pub targets: Vec<String>,
+}
+
+impl Targets {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.targets.is_empty() && !self.all
+    }
+
+    fn repository_root(&self) -> Option<PathBuf> {
+        if self.repo.is_some() {
+            return self.repo.clone();
+        }
+
+        let cwd = PathBuf::from(".");
+        if cwd.join(RECIPES_DIR).is_dir() {
+            Some(cwd)
+        } else {
+            None
+        }
+    }
+
+    /// Loads the repository and all given recipe files, returning the loaded recipes and the
+    /// indices of the selected ones.
+    pub async fn load(&self) -> anyhow::Result<(RecipeRepository, Vec<usize>)> {
+        let mut errors = vec![];
+        let mut repository = RecipeRepository::default();
+
+        if let Some(root) = self.repository_root() {
+            let (loaded, errs) = RecipeRepository::load(root).await?;
+            repository = loaded;
+            errors.extend(errs);
+        }
+
+        let mut from_files = vec![];
+        for file in &self.files {
+            let start = repository.recipes.len();
+            errors.extend(repository.load_file(file).await?);
+            from_files.extend(start..repository.recipes.len());
+        }
+
+        let mut selected = if self.targets.is_empty() {
+            from_files
+        } else {
+            vec![]
+        };
+
+        for target in &self.targets {
+            let path = Path::new(target);
+            if path.is_file() {
+                let start = repository.recipes.len();
+                errors.extend(repository.load_file(path).await?);
+                selected.extend(start..repository.recipes.len());
+                continue;
+            }
+
+            if let Some(idx) = repository.position(target) {
+                selected.push(idx);
+                continue;
+            }
+
+            match repository.providers(target) {
+                [idx] => selected.push(*idx),
+                [] if errors.is_empty() => {
+                    bail!("no recipe file or recipe named \"{}\" found", target)
+                }
+                [] => {}
+                _ => bail!(
+                    "\"{}\" is provided by multiple recipes, select one by name",
+                    target
+                ),
+            }
+        }
+
+        if !errors.is_empty() {
+            for error in errors {
+                eprintln!("{:?}", miette::Error::new(error));
+            }
+
+            bail!("failed to load recipes");
+        }
+
+        if self.all {
+            selected = (0..repository.recipes.len()).collect();
+        }
+
+        Ok((repository, selected))
+    }
+
+    /// Returns the selected recipes, ordered so that dependencies are handled first.
+    pub async fn resolve(&self) -> anyhow::Result<Vec<Recipe>> {
+        let (repository, selected) = self.load().await?;
+        let graph = RecipeGraph::new(&repository.recipes);
+
+        let order = match graph.sorted_from(selected.iter().copied()) {
+            Ok(order) => order,
+            Err(e) => {
+                eprintln!("{:?}", miette::Error::new(e));
+                bail!("can't order recipes");
+            }
+        };
+
+        Ok(order
+            .into_iter()
+            .filter(|idx| self.deps || selected.contains(idx))
+            .map(|idx| repository.recipes[idx].clone())
+            .collect())
+    }
+}
+
+impl Cli {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let config = EngineOptions {
+            config: self.config.as_deref(),
+            jobs: self.jobs,
+            offline: self.offline,
+        };
+
+        match &self.command {
+            Command::Build(targets) => run_stages(config, targets, &Stage::stages()).await,
+            Command::Fetch(targets) => {
+                run_stages(config, targets, &[Stage::Prepare, Stage::Fetch]).await
+            }
+            Command::Extract(targets) => {
+                let stages = [Stage::Prepare, Stage::Fetch, Stage::Extract];
+                run_stages(config, targets, &stages).await
+            }
+            Command::Package(targets) => {
+                let stages = [Stage::Prepare, Stage::Split, Stage::Package];
+                run_stages(config, targets, &stages).await
+            }
+            Command::Info(targets) => info(targets).await,
+            Command::Checksum(targets) => checksum(config, targets).await,
+            Command::Clean(targets) => clean(config, targets).await,
+            Command::Cache { command } => cache(config, command).await,
+            Command::ServeCache { listen } => {
+                create_engine(config).await?.serve_cache(*listen).await
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct EngineOptions<'a> {
+    config: Option<&'a Path>,
+    jobs: Option<usize>,
+    offline: bool,
+}
+
+async fn create_engine(options: EngineOptions<'_>) -> anyhow::Result<Engine> {
+    let mut config = EngineConfig::load(options.config).await?;
+    config.jobs = options.jobs.or(config.jobs);
+    if options.offline {
+        config.offline = Some(true);
+    }
+
+    Ok(Engine::new(config.into_settings()))
+}
+
+async fn prepare(
+    config: EngineOptions<'_>,
+    targets: &Targets,
+) -> anyhow::Result<(Engine, Vec<Recipe>)> {
+    let recipes = targets.resolve().await?;
+    if recipes.is_empty() {
+        bail!("no recipes given");
+    }
+
+    let engine = create_engine(config).await?;
+    engine.prepare_engine().await?;
+
+    Ok((engine, recipes))
+}
+
+async fn run_stages(
+    config: EngineOptions<'_>,
+    targets: &Targets,
+    stages: &[Stage],
+) -> anyhow::Result<()> {
+    let (engine, recipes) = prepare(config, targets).await?;
+    engine.build_recipes(&recipes, stages).await
+}
+
+async fn info(targets: &Targets) -> anyhow::Result<()> {
+    for recipe in targets.resolve().await? {
+        println!("{} {}", recipe.name, recipe.package_version());
+        println!("  description: {}", recipe.description);
+        if let Some(home) = &recipe.home {
+            println!("  home: {}", home);
+        }
+        println!("  license: {}", recipe.license.join(" "));
+        println!("  maintainers: {}", recipe.maintainers.join(", "));
+        println!("  depends: {}", recipe.depends.join(" "));
+        println!("  provides: {}", recipe.provides.join(" "));
+        println!("  style: {:?}", recipe.style.style);
+        println!("  source dir: {}", recipe.source_dir);
+        if let Some(directory) = recipe.directory() {
+            println!("  directory: {}", directory.display());
+        }
+
+        println!("  artifacts:");
+        for artifact in &recipe.artifacts {
+            println!("    {}", describe_artifact(artifact));
+        }
+
+        println!("  sides:");
+        for side in &recipe.sides {
+            println!("    {}: {}", side.name, side.description);
+        }
+    }
+
+    Ok(())
+}
+
+async fn checksum(config: EngineOptions<'_>, targets: &Targets) -> anyhow::Result<()> {
+    let (engine, recipes) = prepare(config, targets).await?;
+
+    for recipe in &recipes {
+        let checksums = engine.missing_checksums(recipe).await?;
+        if checksums.is_empty() {
+            continue;
+        }
+
+        let path = match &recipe.path {
+            Some(path) => path,
+            None => bail!("recipe {} wasn't loaded from a file", recipe.name),
+        };
+
+        add_checksums(path, &recipe.name, &checksums).await?;
+        println!(
+            "[{}] added {} checksums to {}",
+            recipe.name,
+            checksums.len(),
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+async fn clean(config: EngineOptions<'_>, targets: &Targets) -> anyhow::Result<()> {
+    let engine = create_engine(config).await?;
+    if targets.is_empty() {
+        return engine.clean_all().await;
+    }
+
+    for recipe in targets.resolve().await? {
+        engine.clean_recipe(&recipe).await?;
+    }
+
+    Ok(())
+}
+
+fn describe_artifact(artifact: &Artifact) -> String {
+    match &artifact.source {
+        ArtifactSource::Fetch(fetch) => {
+            format!("fetch {}", fetch.urls().collect::<Vec<_>>().join(" "))
+        }
+        ArtifactSource::Git(git) => format!(
+            "git {} {} {}",
+            git.url,
+            git.reference.kind(),
+            git.reference.name()
+        ),
+        ArtifactSource::File(local) => format!("file {}", local.path),
+        ArtifactSource::Patch(patch) => {
+            format!("patch {} (strip {})", patch.file.path, patch.strip)
+        }
+    }
+}
+
+async fn cache(config: EngineOptions<'_>, command: &CacheCommand) -> anyhow::Result<()> {
+    let targets = match command {
+        CacheCommand::List(targets) | CacheCommand::Verify(targets) => targets,
+        CacheCommand::Gc(args) => &args.targets,
+    };
+
+    // whatever the loaded recipes don't use is removed, so they have to be all recipes there are
+    if let CacheCommand::Gc(args) = command {
+        if args.targets.repository_root().is_none() {
+            bail!("cache gc needs a recipe repository, pass --repo or run it inside one");
+        }
+
+        if !args.targets.files.is_empty() || !args.targets.targets.is_empty() {
+            bail!("cache gc keeps what the whole repository uses, it takes no recipe files or targets");
+        }
+    }
+
+    // entries are matched against every loaded recipe, not just the selected ones
+    let (repository, _) = targets.load().await?;
+    let recipes = &repository.recipes;
+    let engine = create_engine(config).await?;
+
+    match command {
+        CacheCommand::List(_) => {
+            for entry in engine.cache_entries(recipes).await? {
+                print_cache_entry(recipes, &entry);
+            }
+        }
+
+        CacheCommand::Verify(_) => {
+            let mut failed = 0;
+            for entry in engine.cache_entries(recipes).await? {
+                match engine.verify_cache_entry(recipes, &entry).await? {
+                    Some(Ok(())) => println!("ok {}", entry.file_name()),
+                    Some(Err(reason)) => {
+                        failed += 1;
+                        println!("FAILED {}: {}", entry.file_name(), reason);
+                    }
+                    None => {}
+                }
+            }
+
+            if failed > 0 {
+                bail!("{} cached artifacts failed verification", failed);
+            }
+        }
+
+        CacheCommand::Gc(args) => {
+            if recipes.is_empty() {
+                bail!("no recipes loaded, refusing to remove the entire cache");
+            }
+
+            let older_than = args
+                .older_than
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60));
+            let removed = engine
+                .collect_cache_garbage(recipes, older_than, args.dry_run)
+                .await?;
+
+            let size: u64 = removed.iter().map(|x| x.size).sum();
+            for entry in &removed {
+                println!("{} {}", entry.file_name(), format_size(entry.size));
+            }
+
+            println!(
+                "{} {} entries ({})",
+                if args.dry_run {
+                    "would remove"
+                } else {
+                    "removed"
+                },
+                removed.len(),
+                format_size(size)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn print_cache_entry(recipes: &[Recipe], entry: &CacheEntry) {
+    println!(
+        "{} ({}, {}, {} days old)",
+        entry.file_name(),
+        entry.kind.name(),
+        format_size(entry.size),
+        entry.age().as_secs() / (24 * 60 * 60)
+    );
+
+    if !entry.is_referenced() {
+        println!("  not used by any recipe");
+    }
+
+    if entry.kind == CacheEntryKind::Partial {
+        return;
+    }
+
+    for (recipe_idx, artifact_idx) in &entry.owners {
+        let recipe = &recipes[*recipe_idx];
+        println!(
+            "  {}: {}",
+            recipe.name,
+            describe_artifact(&recipe.artifacts[*artifact_idx])
+        );
+    }
+}
+
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", size, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
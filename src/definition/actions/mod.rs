@@ -14,6 +14,7 @@ pub enum Stage {
     Fetch,
     Prepare,
     Extract,
+    Patch,
     Configure,
     Build,
     Install,
@@ -22,11 +23,12 @@ pub enum Stage {
 }
 
 impl Stage {
-    pub const fn stages() -> [Stage; 8] {
+    pub const fn stages() -> [Stage; 9] {
         [
             Stage::Prepare,
             Stage::Fetch,
             Stage::Extract,
+            Stage::Patch,
             Stage::Configure,
             Stage::Build,
             Stage::Install,
@@ -46,6 +48,7 @@ impl Stage {
             "fetch" => Stage::Fetch,
             "prepare" => Stage::Prepare,
             "extract" => Stage::Extract,
+            "patch" => Stage::Patch,
             "configure" => Stage::Configure,
             "build" => Stage::Build,
             "install" => Stage::Install,
@@ -62,6 +65,14 @@ pub enum Action {
     Configure,
     Make,
     MakeInstall,
+    CmakeConfigure,
+    CmakeBuild,
+    CmakeInstall,
+    CmakeCheck,
+    MesonSetup,
+    MesonCompile,
+    MesonInstall,
+    MesonTest,
     Bin(BinAction),
     Man(ManAction),
     Link(LinkAction),
@@ -49,6 +49,14 @@ impl ParseNode for Action {
             ".default" => (Action::Default.into(), vec![]),
             "make" => (Action::Make.into(), vec![]),
             "make-install" => (Action::MakeInstall.into(), vec![]),
+            "cmake-configure" => (Action::CmakeConfigure.into(), vec![]),
+            "cmake-build" => (Action::CmakeBuild.into(), vec![]),
+            "cmake-install" => (Action::CmakeInstall.into(), vec![]),
+            "cmake-check" => (Action::CmakeCheck.into(), vec![]),
+            "meson-setup" => (Action::MesonSetup.into(), vec![]),
+            "meson-compile" => (Action::MesonCompile.into(), vec![]),
+            "meson-install" => (Action::MesonInstall.into(), vec![]),
+            "meson-test" => (Action::MesonTest.into(), vec![]),
             "cc" => CcAction::parse_node_with_errors(input).map(Action::Cc),
             "bin" => BinAction::parse_node_with_errors(input).map(Action::Bin),
             "man" => ManAction::parse_node_with_errors(input).map(Action::Man),
@@ -13,6 +13,8 @@ pub enum BuildStyleType {
     Noop,
     GnuConfigure,
     Configure,
+    Cmake,
+    Meson,
 }
 
 impl BuildStyleType {
@@ -21,6 +23,8 @@ impl BuildStyleType {
             "noop" => BuildStyleType::Noop,
             "configure" => BuildStyleType::Configure,
             "gnu-configure" => BuildStyleType::GnuConfigure,
+            "cmake" => BuildStyleType::Cmake,
+            "meson" => BuildStyleType::Meson,
             _ => return None,
         })
     }
@@ -44,4 +48,16 @@ pub struct BuildStyleVariables {
     pub make_use_env: Option<bool>,
     pub make_args: Option<Vec<String>>,
     pub make_env: Option<HashMap<String, String>>,
+
+    pub cmake_args: Option<Vec<String>>,
+    /// `CMAKE_BUILD_TYPE`, defaults to `Release`.
+    pub cmake_build_type: Option<String>,
+    /// Generator passed to `cmake -G`, defaults to the default of cmake.
+    pub cmake_generator: Option<String>,
+
+    pub meson_args: Option<Vec<String>>,
+    /// `--buildtype` of meson, defaults to `release`.
+    pub meson_build_type: Option<String>,
+    /// Cross files for meson, relative paths are resolved against the directory of the recipe.
+    pub meson_cross_files: Option<Vec<String>>,
 }
@@ -0,0 +1,135 @@
+use crate::definition::Recipe;
+use miette::Diagnostic;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Diagnostic, Error)]
+#[error("dependency cycle found between recipes: {}", .cycle.join(" -> "))]
+#[diagnostic(help("remove one of the depends in the cycle to be able to build these recipes"))]
+pub struct HobDependencyCycleError {
+    pub cycle: Vec<String>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Mark {
+    New,
+    Visiting,
+    Done,
+}
+
+/// Dependencies between recipes, resolved through names, `provides` and sides.
+///
+/// Dependencies that are not provided by any of the given recipes are assumed to be satisfied
+/// by the system and are ignored.
+#[derive(Debug)]
+pub struct RecipeGraph<'a> {
+    recipes: &'a [Recipe],
+    edges: Vec<Vec<usize>>,
+}
+
+impl<'a> RecipeGraph<'a> {
+    pub fn new(recipes: &'a [Recipe]) -> Self {
+        let mut providers: HashMap<String, usize> = HashMap::new();
+
+        for (idx, recipe) in recipes.iter().enumerate() {
+            providers.insert(recipe.name.clone(), idx);
+            providers.insert(recipe.template_vars().self_ref, idx);
+
+            for provide in &recipe.provides {
+                providers.insert(dependency_name(provide).to_string(), idx);
+            }
+
+            for side in &recipe.sides {
+                providers.insert(side.name.clone(), idx);
+                providers.insert(format!("{}-{}", side.name, recipe.package_version()), idx);
+            }
+        }
+
+        let mut edges = vec![];
+        for (idx, recipe) in recipes.iter().enumerate() {
+            let mut deps = vec![];
+
+            let all_depends = recipe
+                .depends
+                .iter()
+                .chain(recipe.sides.iter().flat_map(|side| side.depends.iter()));
+
+            for dep in all_depends {
+                let found = providers
+                    .get(dep.as_str())
+                    .or_else(|| providers.get(dependency_name(dep)));
+
+                if let Some(dep_idx) = found.copied() {
+                    if dep_idx != idx && !deps.contains(&dep_idx) {
+                        deps.push(dep_idx);
+                    }
+                }
+            }
+
+            edges.push(deps);
+        }
+
+        RecipeGraph { recipes, edges }
+    }
+
+    pub fn dependencies(&self, idx: usize) -> &[usize] {
+        &self.edges[idx]
+    }
+
+    /// Returns the given recipes and everything they depend on, dependencies first.
+    pub fn sorted_from(
+        &self,
+        roots: impl IntoIterator<Item = usize>,
+    ) -> Result<Vec<usize>, HobDependencyCycleError> {
+        let mut marks = vec![Mark::New; self.recipes.len()];
+        let mut path = vec![];
+        let mut order = vec![];
+
+        for root in roots {
+            self.visit(root, &mut marks, &mut path, &mut order)?;
+        }
+
+        Ok(order)
+    }
+
+    fn visit(
+        &self,
+        idx: usize,
+        marks: &mut [Mark],
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), HobDependencyCycleError> {
+        match marks[idx] {
+            Mark::Done => return Ok(()),
+            Mark::Visiting => {
+                let start = path.iter().position(|x| *x == idx).unwrap();
+                let cycle = path[start..]
+                    .iter()
+                    .chain([idx].iter())
+                    .map(|x| self.recipes[*x].name.clone())
+                    .collect();
+
+                return Err(HobDependencyCycleError { cycle });
+            }
+            Mark::New => {}
+        }
+
+        marks[idx] = Mark::Visiting;
+        path.push(idx);
+
+        for dep in &self.edges[idx] {
+            self.visit(*dep, marks, path, order)?;
+        }
+
+        path.pop();
+        marks[idx] = Mark::Done;
+        order.push(idx);
+
+        Ok(())
+    }
+}
+
+/// Strips version constraints from a dependency, e.g. `musl>=1.2` becomes `musl`.
+pub fn dependency_name(dep: &str) -> &str {
+    dep.split(['<', '>', '=', '~']).next().unwrap_or(dep).trim()
+}
@@ -1,6 +1,10 @@
 pub mod actions;
 pub mod build_style;
+pub mod graph;
 pub mod parsing;
+pub mod repository;
+pub mod rewrite;
+pub mod template;
 
 use crate::definition::actions::{ActionPlaybook, Stage};
 use crate::definition::build_style::BuildStyle;
@@ -9,6 +13,7 @@ use ring::digest::{Context, SHA256};
 use serde::Serialize;
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(Default, Debug, Clone)]
 pub struct Document {
@@ -32,6 +37,8 @@ pub struct Recipe {
     pub sides: Vec<Side>,
     pub options: RecipeOptions,
     pub playbooks: HashMap<Stage, ActionPlaybook>,
+    #[skip]
+    pub path: Option<PathBuf>,
 }
 
 #[derive(Default, Debug, Clone, ObjectTraversal)]
@@ -50,9 +57,25 @@ pub struct RecipeTemplate {
 }
 
 impl Recipe {
+    /// Directory containing the recipe file, relative files of the recipe are resolved from here.
+    pub fn directory(&self) -> Option<&Path> {
+        self.path.as_deref().and_then(Path::parent)
+    }
+
+    /// Version including the revision, e.g. `1.2.3-r1`.
+    pub fn package_version(&self) -> String {
+        format!("{}-r{}", self.version, self.revision)
+    }
+
+    /// Unique key for a build of this recipe, used to keep the build directories of different
+    /// versions and revisions apart.
+    pub fn build_key(&self) -> String {
+        format!("{}-{}", self.name, self.package_version())
+    }
+
     pub fn template_vars(&self) -> RecipeTemplate {
         RecipeTemplate {
-            self_ref: format!("{}-{}-r{}", self.name, self.version, self.revision),
+            self_ref: self.build_key(),
             name: self.name.clone(),
             version: self.version.clone(),
             revision: self.revision,
@@ -64,8 +87,35 @@ impl Recipe {
 #[derive(Debug, Clone, ObjectTraversal)]
 pub struct Artifact {
     pub source: ArtifactSource,
-    #[skip]
     pub verification: Verification,
+    pub extraction: Extraction,
+}
+
+/// Where and how an artifact ends up in the sources of a recipe.
+#[derive(Debug, Clone, ObjectTraversal)]
+pub struct Extraction {
+    /// Unpack archives, otherwise the file is copied as is.
+    pub extract: bool,
+    /// Leading path components removed from every entry of an archive.
+    pub strip_components: usize,
+    /// Directory relative to the sources of the recipe to put the artifact in.
+    pub destination: Option<String>,
+}
+
+impl Default for Extraction {
+    fn default() -> Self {
+        Extraction {
+            extract: true,
+            strip_components: 0,
+            destination: None,
+        }
+    }
+}
+
+impl Extraction {
+    pub fn is_default(&self) -> bool {
+        self.extract && self.strip_components == 0 && self.destination.is_none()
+    }
 }
 
 impl Artifact {
@@ -88,24 +138,53 @@ impl Artifact {
 #[derive(Debug, Clone, ObjectTraversal)]
 pub enum ArtifactSource {
     Fetch(FetchArtifact),
+    Git(GitArtifact),
+    File(LocalArtifact),
+    Patch(PatchArtifact),
 }
 
 impl ArtifactSource {
     pub fn method_name(&self) -> &[u8] {
         match self {
             ArtifactSource::Fetch(_) => b"fetch",
+            ArtifactSource::Git(_) => b"git",
+            ArtifactSource::File(_) => b"file",
+            ArtifactSource::Patch(_) => b"patch",
         }
     }
 
     pub fn file_name(&self) -> &str {
         match self {
             ArtifactSource::Fetch(f) => f.file_name(),
+            ArtifactSource::Git(g) => g.file_name(),
+            ArtifactSource::File(l) => l.file_name(),
+            ArtifactSource::Patch(p) => p.file.file_name(),
+        }
+    }
+
+    /// The file next to the recipe this artifact refers to, if any.
+    pub fn local(&self) -> Option<&LocalArtifact> {
+        match self {
+            ArtifactSource::File(l) => Some(l),
+            ArtifactSource::Patch(p) => Some(&p.file),
+            _ => None,
+        }
+    }
+
+    pub fn local_mut(&mut self) -> Option<&mut LocalArtifact> {
+        match self {
+            ArtifactSource::File(l) => Some(l),
+            ArtifactSource::Patch(p) => Some(&mut p.file),
+            _ => None,
         }
     }
 
     pub fn hash_data(&self) -> Cow<'_, [u8]> {
         match self {
             ArtifactSource::Fetch(f) => f.hash_data(),
+            ArtifactSource::Git(g) => g.hash_data(),
+            ArtifactSource::File(l) => l.hash_data(),
+            ArtifactSource::Patch(p) => p.file.hash_data(),
         }
     }
 }
@@ -113,7 +192,12 @@ impl ArtifactSource {
 #[derive(Default, Debug, Clone, ObjectTraversal)]
 pub struct FetchArtifact {
     pub url: String,
+    /// Other urls serving the same file, tried in order when `url` fails.
+    pub mirrors: Vec<String>,
     pub file_name: String,
+    /// Overrides the format guessed from the contents and name of the file.
+    #[skip]
+    pub format: Option<ArchiveFormat>,
 }
 
 impl FetchArtifact {
@@ -121,14 +205,281 @@ impl FetchArtifact {
         self.file_name.as_str()
     }
 
+    pub fn urls(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.url.as_str()).chain(self.mirrors.iter().map(String::as_str))
+    }
+
     pub fn hash_data(&self) -> Cow<'_, [u8]> {
         self.url.as_bytes().into()
     }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Archive {
+    Zip,
+    Tar,
+    Cpio,
+    Deb,
+    Rpm,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Bz,
+    Zstd,
+    Lzma,
+    Lzip,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct ArchiveFormat {
+    pub archive: Archive,
+    pub compression: Compression,
+}
+
+/// Known extensions, matched against the end of file names and against formats in recipes.
+const GUESSES: &[(&str, Archive, Compression)] = &[
+    (".tar.gz", Archive::Tar, Compression::Gzip),
+    (".tgz", Archive::Tar, Compression::Gzip),
+    (".tar.xz", Archive::Tar, Compression::Xz),
+    (".txz", Archive::Tar, Compression::Xz),
+    (".tar.bz2", Archive::Tar, Compression::Bz),
+    (".tar.bz", Archive::Tar, Compression::Bz),
+    (".tbz2", Archive::Tar, Compression::Bz),
+    (".tbz", Archive::Tar, Compression::Bz),
+    (".tar.zst", Archive::Tar, Compression::Zstd),
+    (".tzst", Archive::Tar, Compression::Zstd),
+    (".tar.lzma", Archive::Tar, Compression::Lzma),
+    (".tlzma", Archive::Tar, Compression::Lzma),
+    (".tar.lz", Archive::Tar, Compression::Lzip),
+    (".tlz", Archive::Tar, Compression::Lzip),
+    (".tar", Archive::Tar, Compression::None),
+    (".zip", Archive::Zip, Compression::None),
+    (".cpio.gz", Archive::Cpio, Compression::Gzip),
+    (".cpio.xz", Archive::Cpio, Compression::Xz),
+    (".cpio.zst", Archive::Cpio, Compression::Zstd),
+    (".cpio", Archive::Cpio, Compression::None),
+    (".deb", Archive::Deb, Compression::None),
+    (".rpm", Archive::Rpm, Compression::None),
+];
+
+impl ArchiveFormat {
+    /// Parses a format as written in recipes, which is its extension, e.g. `tar.gz` or `tgz`.
+    pub fn parse<T: AsRef<str>>(data: T) -> Option<ArchiveFormat> {
+        let data = data.as_ref().trim_start_matches('.');
+        GUESSES
+            .iter()
+            .find(|(ext, _, _)| ext[1..].eq_ignore_ascii_case(data))
+            .map(|(_, archive, compression)| ArchiveFormat::new(*archive, *compression))
+    }
+
+    /// Guesses the format from the extension of a file name.
+    pub fn from_file_name<T: AsRef<str>>(file_name: T) -> Option<ArchiveFormat> {
+        let file_name = file_name.as_ref().to_ascii_lowercase();
+        GUESSES
+            .iter()
+            .find(|(ext, _, _)| file_name.ends_with(ext))
+            .map(|(_, archive, compression)| ArchiveFormat::new(*archive, *compression))
+    }
+
+    pub fn new(archive: Archive, compression: Compression) -> Self {
+        ArchiveFormat {
+            archive,
+            compression,
+        }
+    }
+}
+
+/// A file shipped next to the recipe, like `files/foo.conf` or `patches/fix.patch`.
+#[derive(Default, Debug, Clone, ObjectTraversal)]
+pub struct LocalArtifact {
+    /// Path relative to the directory of the recipe.
+    pub path: String,
+    pub file_name: String,
+    /// Sha256 of the contents, filled in when the recipe is loaded from a file.
+    #[skip]
+    pub digest: Option<[u8; 32]>,
+}
+
+impl LocalArtifact {
+    pub fn file_name(&self) -> &str {
+        self.file_name.as_str()
+    }
+
+    /// The path and the contents of the file, so that editing it invalidates the cache.
+    pub fn hash_data(&self) -> Cow<'_, [u8]> {
+        let mut data = self.path.as_bytes().to_vec();
+        data.push(0);
+        if let Some(digest) = &self.digest {
+            data.extend_from_slice(digest);
+        }
+
+        data.into()
+    }
+
+    /// Resolves the path of the file against the directory of `recipe`.
+    pub fn resolve(&self, recipe: &Recipe) -> Option<PathBuf> {
+        recipe.directory().map(|dir| dir.join(&self.path))
+    }
+}
+
+#[derive(Default, Debug, Clone, ObjectTraversal)]
+pub struct PatchArtifact {
+    pub file: LocalArtifact,
+    /// Amount of leading path components stripped from file names in the patch, like `-p`.
+    pub strip: usize,
+}
+
+#[derive(Debug, Clone, ObjectTraversal)]
+pub struct GitArtifact {
+    pub url: String,
+    pub reference: GitReference,
+    pub submodules: bool,
+    /// Name of the directory the repository is checked out into, inside the source directory.
+    pub file_name: String,
+}
+
+impl GitArtifact {
+    pub fn file_name(&self) -> &str {
+        self.file_name.as_str()
+    }
+
+    /// Name of the repository in its url, which the mirror is cached as.
+    pub fn mirror_name(&self) -> &str {
+        let name = self.url.trim_end_matches('/').rsplit('/').next().unwrap();
+        name.strip_suffix(".git").unwrap_or(name)
+    }
+
+    /// Only the url, which keys the mirror in the cache that every reference of the repository
+    /// shares. The commit a reference resolves to keys the sources instead, see
+    /// `FetchedArtifact::source_id`.
+    pub fn hash_data(&self) -> Cow<'_, [u8]> {
+        self.url.as_bytes().into()
+    }
+}
+
+#[derive(Debug, Clone, ObjectTraversal)]
+pub enum GitReference {
+    Rev(String),
+    Tag(String),
+    Branch(String),
+}
+
+impl GitReference {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GitReference::Rev(_) => "rev",
+            GitReference::Tag(_) => "tag",
+            GitReference::Branch(_) => "branch",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            GitReference::Rev(n) | GitReference::Tag(n) | GitReference::Branch(n) => n,
+        }
+    }
+
+    /// Revision expression that resolves this reference to a commit in a mirror.
+    pub fn rev_spec(&self) -> String {
+        match self {
+            GitReference::Rev(rev) => format!("{}^{{commit}}", rev),
+            GitReference::Tag(tag) => format!("refs/tags/{}^{{commit}}", tag),
+            GitReference::Branch(branch) => format!("refs/heads/{}^{{commit}}", branch),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, ObjectTraversal)]
 pub struct Verification {
-    pub sha256: Option<[u8; 32]>,
+    #[skip]
+    pub checksums: Vec<Checksum>,
+    pub signature: Option<Signature>,
+}
+
+/// A detached signature published next to an artifact.
+#[derive(Debug, Clone, ObjectTraversal)]
+pub struct Signature {
+    #[skip]
+    pub kind: SignatureKind,
+    /// Where to fetch the signature from, defaults to the url of the artifact with the usual
+    /// extension for the kind of signature appended.
+    pub url: Option<String>,
+    /// Public key or keyring to verify against, relative to the directory of the recipe.
+    pub key: String,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SignatureKind {
+    /// Checked with `gpgv`, the key should be an exported keyring (`gpg --export`).
+    OpenPgp,
+    Minisign,
+    Signify,
+}
+
+impl SignatureKind {
+    pub fn parse<T: AsRef<str>>(data: T) -> Option<SignatureKind> {
+        Some(match data.as_ref() {
+            "openpgp" | "pgp" | "gpg" => SignatureKind::OpenPgp,
+            "minisign" => SignatureKind::Minisign,
+            "signify" => SignatureKind::Signify,
+            _ => return None,
+        })
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SignatureKind::OpenPgp | SignatureKind::Signify => "sig",
+            SignatureKind::Minisign => "minisig",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Sha512,
+    /// BLAKE2b with a 512 bit digest, as printed by `b2sum`.
+    Blake2b,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn parse<T: AsRef<str>>(data: T) -> Option<ChecksumAlgorithm> {
+        Some(match data.as_ref() {
+            "sha256" => ChecksumAlgorithm::Sha256,
+            "sha512" => ChecksumAlgorithm::Sha512,
+            "blake2b" => ChecksumAlgorithm::Blake2b,
+            "blake3" => ChecksumAlgorithm::Blake3,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha512 => "sha512",
+            ChecksumAlgorithm::Blake2b => "blake2b",
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Length of the digest in bytes.
+    pub fn digest_len(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Sha256 | ChecksumAlgorithm::Blake3 => 32,
+            ChecksumAlgorithm::Sha512 | ChecksumAlgorithm::Blake2b => 64,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, ObjectTraversal)]
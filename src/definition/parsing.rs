@@ -1,12 +1,15 @@
 use crate::definition::actions::ActionPlaybook;
 use crate::definition::build_style::{BuildStyle, BuildStyleType, BuildStyleVariables};
 use crate::definition::{
-    Artifact, ArtifactSource, FetchArtifact, RecipeOptions, Side, Verification,
+    ArchiveFormat, Artifact, ArtifactSource, Checksum, ChecksumAlgorithm, Extraction,
+    FetchArtifact, GitArtifact, GitReference, LocalArtifact, PatchArtifact, RecipeOptions, Side,
+    Signature, SignatureKind, Verification,
 };
 use crate::{Document, Recipe};
 use kdl::{KdlDocument, KdlNode};
 use miette::{Diagnostic, NamedSource, SourceSpan};
 use std::collections::HashMap;
+use std::path::Path;
 use thiserror::Error;
 
 #[derive(Debug, Diagnostic, Error)]
@@ -213,6 +216,25 @@ macro_rules! parse_bool_into {
     };
 }
 
+#[macro_export]
+macro_rules! parse_usize_into {
+    ($input:ident, $into:expr, $errors:expr, $name:literal) => {
+        use $crate::definition::parsing::extract_single_usize_value;
+
+        match extract_single_usize_value(
+            $input,
+            concat!($name, " missing"),
+            concat!($name, " should be an integer"),
+            concat!($name, " should not be negative"),
+            concat!("only 1 integer expected for ", $name),
+            concat!($name, " expected a value, property found instead"),
+        ) {
+            Ok(n) => $into = n.into(),
+            Err(e) => $errors.push(e),
+        };
+    };
+}
+
 #[macro_export]
 macro_rules! parse_string_list_into {
     ($input:ident, $into:ident, $errors:expr, $name:literal) => {
@@ -320,7 +342,7 @@ impl ParseNode for Recipe {
         let mut depends: Vec<String> = vec![];
         let mut provides: Vec<String> = vec![];
         let mut artifacts: Vec<Artifact> = vec![];
-        let revision: usize = 0;
+        let mut revision: usize = 0;
         let mut style: Option<BuildStyle> = None;
         let mut playbooks = HashMap::new();
 
@@ -332,6 +354,10 @@ impl ParseNode for Recipe {
                     parse_string_into!(node, version, errors, "version");
                 }
 
+                "revision" => {
+                    parse_usize_into!(node, revision, errors, "revision");
+                }
+
                 "description" => {
                     parse_string_into!(node, description, errors, "version");
                 }
@@ -398,7 +424,7 @@ impl ParseNode for Recipe {
                     }
                 }
 
-                "install" | "prepare" | "build" | "extract" | "configure" => {
+                "install" | "prepare" | "build" | "extract" | "patch" | "configure" => {
                     let (playbook, err) = ActionPlaybook::parse_node_with_errors(node);
                     errors.extend(err);
 
@@ -436,6 +462,7 @@ impl ParseNode for Recipe {
             sides: vec![],
             options: options.unwrap_or_default(),
             playbooks,
+            path: None,
         };
 
         let mut sides = vec![];
@@ -493,16 +520,51 @@ impl ParseNode for Artifact {
         let (source, err) = ArtifactSource::parse_node_with_errors(input);
         errors.extend(err);
 
-        let source = if let Some(source) = source {
-            source
+        let (extraction, err) = Extraction::parse_node_with_errors(input);
+        errors.extend(err);
+
+        let (source, extraction) = if let (Some(source), Some(extraction)) = (source, extraction) {
+            (source, extraction)
         } else {
             return (None, errors);
         };
 
+        if matches!(source, ArtifactSource::Patch(_)) && !extraction.is_default() {
+            errors.push(HobParseError {
+                span: *input.span(),
+                label: None,
+                help: Some(
+                    "patches are applied to the extracted sources by the patch stage".into(),
+                ),
+                kind: "patch artifacts can't be extracted",
+            });
+        }
+
+        let copied = matches!(source, ArtifactSource::Git(_) | ArtifactSource::File(_));
+        if copied && (!extraction.extract || extraction.strip_components > 0) {
+            errors.push(HobParseError {
+                span: *input.span(),
+                label: None,
+                help: Some("only fetched archives are unpacked, use `destination` instead".into()),
+                kind: "git and file artifacts can't use extract or strip-components",
+            });
+        }
+
+        let verified = !verification.checksums.is_empty() || verification.signature.is_some();
+        if matches!(source, ArtifactSource::Git(_)) && verified {
+            errors.push(HobParseError {
+                span: *input.span(),
+                label: None,
+                help: Some("pin the artifact to a commit with `rev` instead".into()),
+                kind: "git artifacts can't be verified with checksums or signatures",
+            });
+        }
+
         (
             Some(Artifact {
                 source,
                 verification,
+                extraction,
             }),
             errors,
         )
@@ -520,6 +582,21 @@ impl ParseNode for ArtifactSource {
                 (obj.map(ArtifactSource::Fetch), err)
             }
 
+            "git" => {
+                let (obj, err) = GitArtifact::parse_node_with_errors(input);
+                (obj.map(ArtifactSource::Git), err)
+            }
+
+            "file" => {
+                let (obj, err) = LocalArtifact::parse_node_with_errors(input);
+                (obj.map(ArtifactSource::File), err)
+            }
+
+            "patch" => {
+                let (obj, err) = PatchArtifact::parse_node_with_errors(input);
+                (obj.map(ArtifactSource::Patch), err)
+            }
+
             _ => (
                 None,
                 vec![HobParseError {
@@ -533,29 +610,104 @@ impl ParseNode for ArtifactSource {
     }
 }
 
+impl ParseNode for Extraction {
+    fn parse_node_with_errors(input: &KdlNode) -> (Option<Self>, Vec<HobParseError>)
+    where
+        Self: Sized,
+    {
+        let mut errors = vec![];
+        let mut extraction = Extraction::default();
+
+        for node in input.nodes() {
+            match node.name().value() {
+                "extract" => {
+                    parse_bool_into!(node, extraction.extract, errors, "extract");
+                }
+
+                "strip-components" => {
+                    parse_usize_into!(
+                        node,
+                        extraction.strip_components,
+                        errors,
+                        "strip-components"
+                    );
+                }
+
+                "destination" => {
+                    let mut destination: Option<String> = None;
+                    parse_string_into!(node, destination, errors, "destination");
+
+                    match destination {
+                        Some(destination) if !is_relative_path(&destination) => {
+                            errors.push(HobParseError {
+                                span: *node.entries().first().unwrap().span(),
+                                label: None,
+                                help: None,
+                                kind: "destination should be relative to the sources of the recipe",
+                            })
+                        }
+                        destination => extraction.destination = destination,
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        (Some(extraction), errors)
+    }
+}
+
+/// Whether `path` is relative and stays below the directory it is relative to.
+fn is_relative_path(path: &str) -> bool {
+    !Path::new(path).is_absolute() && !path.split('/').any(|x| x == "..")
+}
+
 impl ParseNode for FetchArtifact {
     fn parse_node_with_errors(input: &KdlNode) -> (Option<Self>, Vec<HobParseError>)
     where
         Self: Sized,
     {
-        let mut url = None;
+        let mut urls: Vec<String> = vec![];
         let mut errors = vec![];
         let mut file_name = None;
+        let mut format = None;
         for node in input.nodes() {
             match node.name().value() {
                 "url" => {
-                    parse_string_into!(node, url, errors, "url of artifact");
+                    parse_string_list_into!(node, urls, errors, "url of artifact");
                 }
 
                 "name" => {
                     parse_string_into!(node, file_name, errors, "name of artifact");
                 }
 
+                "format" => {
+                    let mut name: Option<String> = None;
+                    parse_string_into!(node, name, errors, "format of artifact");
+
+                    if let Some(name) = name {
+                        format = ArchiveFormat::parse(&name);
+                        if format.is_none() {
+                            errors.push(HobParseError {
+                                span: *node.entries().first().unwrap().span(),
+                                label: None,
+                                help: Some(
+                                    "formats are named by their extension, like tar.gz or zip"
+                                        .into(),
+                                ),
+                                kind: "unknown archive format",
+                            });
+                        }
+                    }
+                }
+
                 _ => {}
             }
         }
 
-        let res = if let Some(url) = url {
+        let res = if !urls.is_empty() {
+            let url = urls.remove(0);
             Some(FetchArtifact {
                 file_name: file_name.unwrap_or_else(|| {
                     url.rsplit('/')
@@ -567,6 +719,8 @@ impl ParseNode for FetchArtifact {
                         .to_string()
                 }),
                 url,
+                mirrors: urls,
+                format,
             })
         } else {
             errors.push(HobParseError {
@@ -582,42 +736,290 @@ impl ParseNode for FetchArtifact {
     }
 }
 
+impl ParseNode for LocalArtifact {
+    fn parse_node_with_errors(input: &KdlNode) -> (Option<Self>, Vec<HobParseError>)
+    where
+        Self: Sized,
+    {
+        let mut path: Option<String> = None;
+        let mut errors = vec![];
+        parse_string_into!(input, path, errors, "path of local artifact");
+
+        let path = if let Some(path) = path {
+            path
+        } else {
+            return (None, errors);
+        };
+
+        if !is_relative_path(&path) {
+            errors.push(HobParseError {
+                span: *input.entries().first().unwrap().span(),
+                label: None,
+                help: Some("local artifacts should live in the directory of the recipe".into()),
+                kind: "path of local artifact should be relative to the recipe",
+            });
+
+            return (None, errors);
+        }
+
+        let file_name = path.rsplit('/').next().unwrap().to_string();
+
+        (
+            Some(LocalArtifact {
+                path,
+                file_name,
+                digest: None,
+            }),
+            errors,
+        )
+    }
+}
+
+impl ParseNode for PatchArtifact {
+    fn parse_node_with_errors(input: &KdlNode) -> (Option<Self>, Vec<HobParseError>)
+    where
+        Self: Sized,
+    {
+        let (file, mut errors) = LocalArtifact::parse_node_with_errors(input);
+        let mut strip: usize = 1;
+
+        for node in input.nodes() {
+            if node.name().value() == "strip" {
+                parse_usize_into!(node, strip, errors, "strip");
+            }
+        }
+
+        (file.map(|file| PatchArtifact { file, strip }), errors)
+    }
+}
+
+impl ParseNode for GitArtifact {
+    fn parse_node_with_errors(input: &KdlNode) -> (Option<Self>, Vec<HobParseError>)
+    where
+        Self: Sized,
+    {
+        let mut url: Option<String> = None;
+        let mut errors = vec![];
+        let mut file_name: Option<String> = None;
+        let mut submodules = false;
+        let mut references = vec![];
+
+        for node in input.nodes() {
+            let mut reference: Option<String> = None;
+            match node.name().value() {
+                "url" => {
+                    parse_string_into!(node, url, errors, "url of artifact");
+                }
+
+                "name" => {
+                    parse_string_into!(node, file_name, errors, "name of artifact");
+                }
+
+                "submodules" => {
+                    parse_bool_into!(node, submodules, errors, "submodules");
+                }
+
+                "rev" => {
+                    parse_string_into!(node, reference, errors, "rev");
+                    references.extend(reference.map(|x| (node, GitReference::Rev(x))));
+                }
+
+                "tag" => {
+                    parse_string_into!(node, reference, errors, "tag");
+                    references.extend(reference.map(|x| (node, GitReference::Tag(x))));
+                }
+
+                "branch" => {
+                    parse_string_into!(node, reference, errors, "branch");
+                    references.extend(reference.map(|x| (node, GitReference::Branch(x))));
+                }
+
+                _ => {}
+            }
+        }
+
+        for (node, _) in references.iter().skip(1) {
+            errors.push(HobParseError {
+                span: *node.span(),
+                label: None,
+                help: Some("only one of rev, tag or branch can be given".to_string()),
+                kind: "git artifact has multiple references",
+            });
+        }
+
+        let reference = references
+            .into_iter()
+            .next()
+            .map(|(_, reference)| reference);
+        if reference.is_none() {
+            errors.push(HobParseError {
+                span: *input.span(),
+                label: None,
+                help: Some("add a rev, tag or branch to check out".to_string()),
+                kind: "git artifact requires a reference to be given",
+            });
+        }
+
+        if url.is_none() {
+            errors.push(HobParseError {
+                span: *input.span(),
+                label: None,
+                help: None,
+                kind: "git artifact requires an url to be given",
+            });
+        }
+
+        let (url, reference) = match (url, reference) {
+            (Some(url), Some(reference)) => (url, reference),
+            _ => return (None, errors),
+        };
+
+        let mut res = GitArtifact {
+            file_name: String::new(),
+            url,
+            reference,
+            submodules,
+        };
+        res.file_name = file_name.unwrap_or_else(|| res.mirror_name().to_string());
+
+        (Some(res), errors)
+    }
+}
+
 impl ParseNode for Verification {
     fn parse_node_with_errors(input: &KdlNode) -> (Option<Self>, Vec<HobParseError>)
     where
         Self: Sized,
     {
         let mut errors = vec![];
-        let mut sha256 = None;
+        let mut checksums = vec![];
+        let mut signature = None;
+        for node in input.nodes() {
+            if node.name().value() == "signature" {
+                let (sig, err) = Signature::parse_node_with_errors(node);
+                errors.extend(err);
+                signature = sig.or(signature);
+                continue;
+            }
+
+            let algorithm = if let Some(algorithm) = ChecksumAlgorithm::parse(node.name().value()) {
+                algorithm
+            } else {
+                continue;
+            };
+
+            let mut str_sum: Option<String> = None;
+            parse_string_into!(node, str_sum, errors, "checksum");
+
+            // accept `sha256sum` style lines as well, so published checksums can be copied as is
+            let str_sum = match str_sum.as_deref().and_then(|x| x.split_whitespace().next()) {
+                Some(str_sum) => str_sum,
+                None => continue,
+            };
+
+            match hex::decode(str_sum) {
+                Ok(v) if v.len() != algorithm.digest_len() => errors.push(HobParseError {
+                    span: *node.entries().first().unwrap().span(),
+                    label: None,
+                    help: Some(format!(
+                        "expected {} byte long hex string for {}, found {} bytes",
+                        algorithm.digest_len(),
+                        algorithm.name(),
+                        v.len()
+                    )),
+                    kind: "checksum has the wrong length",
+                }),
+                Ok(digest) => checksums.push(Checksum { algorithm, digest }),
+                Err(v) => errors.push(HobParseError {
+                    span: *node.entries().first().unwrap().span(),
+                    label: None,
+                    help: Some(format!("{}", v)),
+                    kind: "invalid hex string",
+                }),
+            }
+        }
+
+        (
+            Some(Verification {
+                checksums,
+                signature,
+            }),
+            errors,
+        )
+    }
+}
+
+impl ParseNode for Signature {
+    fn parse_node_with_errors(input: &KdlNode) -> (Option<Self>, Vec<HobParseError>)
+    where
+        Self: Sized,
+    {
+        let mut errors = vec![];
+        let mut kind: Option<String> = None;
+        let mut url = None;
+        let mut key: Option<String> = None;
+        let mut key_node = None;
+
+        parse_string_into!(input, kind, errors, "kind of signature");
+
         for node in input.nodes() {
             match node.name().value() {
-                "sha256" => {
-                    let mut str_sha = None;
-                    parse_string_into!(node, str_sha, errors, "sha256");
+                "url" => {
+                    parse_string_into!(node, url, errors, "url of signature");
+                }
 
-                    if let Some(str_sha) = str_sha {
-                        match hex::decode(str_sha) {
-                            Ok(v) if v.len() != 32 => errors.push(HobParseError {
-                                span: *node.entries().first().unwrap().span(),
-                                label: None,
-                                help: None,
-                                kind: "expected 32 byte long hex string for sha256",
-                            }),
-                            Ok(v) => sha256 = Some(v.try_into().unwrap()),
-                            Err(v) => errors.push(HobParseError {
-                                span: *node.entries().first().unwrap().span(),
-                                label: None,
-                                help: Some(format!("{}", v)),
-                                kind: "invalid hex string",
-                            }),
-                        }
-                    }
+                "key" => {
+                    parse_string_into!(node, key, errors, "key of signature");
+                    key_node = Some(node);
                 }
+
                 _ => {}
             }
         }
 
-        (Some(Verification { sha256 }), errors)
+        let kind = match kind {
+            Some(kind) => kind,
+            None => return (None, errors),
+        };
+
+        let kind = if let Some(kind) = SignatureKind::parse(kind) {
+            kind
+        } else {
+            errors.push(HobParseError {
+                span: *input.entries().first().unwrap().span(),
+                label: None,
+                help: Some("supported are openpgp, minisign and signify".into()),
+                kind: "unknown kind of signature",
+            });
+
+            return (None, errors);
+        };
+
+        let key = if let Some(key) = key {
+            key
+        } else {
+            errors.push(HobParseError {
+                span: *input.span(),
+                label: None,
+                help: Some("add the path of the key, relative to the recipe".into()),
+                kind: "signature requires a key to be given",
+            });
+
+            return (None, errors);
+        };
+
+        if !is_relative_path(&key) {
+            errors.push(HobParseError {
+                span: *key_node.unwrap().entries().first().unwrap().span(),
+                label: None,
+                help: Some("keys should live in the directory of the recipe".into()),
+                kind: "key of signature should be relative to the recipe",
+            });
+
+            return (None, errors);
+        }
+
+        (Some(Signature { kind, url, key }), errors)
     }
 }
 
@@ -662,18 +1064,47 @@ impl ParseNode for BuildStyleVariables {
         for node in input.nodes() {
             match node.name().value() {
                 "configure-script" => {
-                    parse_string_into!(input, vars.configure_script, errors, "configure script");
+                    parse_string_into!(node, vars.configure_script, errors, "configure script");
                 }
 
                 "configure-args" => {
                     parse_string_list_ext_into!(
-                        input,
+                        node,
                         vars.configure_args,
                         errors,
                         "configure args"
                     );
                 }
 
+                "cmake-args" => {
+                    parse_string_list_ext_into!(node, vars.cmake_args, errors, "cmake args");
+                }
+
+                "cmake-build-type" => {
+                    parse_string_into!(node, vars.cmake_build_type, errors, "cmake build type");
+                }
+
+                "cmake-generator" => {
+                    parse_string_into!(node, vars.cmake_generator, errors, "cmake generator");
+                }
+
+                "meson-args" => {
+                    parse_string_list_ext_into!(node, vars.meson_args, errors, "meson args");
+                }
+
+                "meson-build-type" => {
+                    parse_string_into!(node, vars.meson_build_type, errors, "meson build type");
+                }
+
+                "meson-cross-file" => {
+                    parse_string_list_ext_into!(
+                        node,
+                        vars.meson_cross_files,
+                        errors,
+                        "meson cross file"
+                    );
+                }
+
                 _ => {}
             }
         }
@@ -750,7 +1181,7 @@ impl ParseNode for RecipeOptions {
     }
 }
 
-fn extract_single_bool_value(
+pub(crate) fn extract_single_bool_value(
     input: &KdlNode,
     missing_error: &'static str,
     wrong_type_error: &'static str,
@@ -808,6 +1239,70 @@ fn extract_single_bool_value(
     }
 }
 
+pub(crate) fn extract_single_usize_value(
+    input: &KdlNode,
+    missing_error: &'static str,
+    wrong_type_error: &'static str,
+    negative_error: &'static str,
+    too_many_error: &'static str,
+    property_found_error: &'static str,
+) -> Result<usize, HobParseError> {
+    match input.entries().len() {
+        0 => Err(HobParseError {
+            span: *input.name().span(),
+            label: None,
+            help: None,
+            kind: missing_error,
+        }),
+
+        1 => {
+            let name_entry = input.entries().first().unwrap();
+
+            if name_entry.name().is_some() {
+                return Err(HobParseError {
+                    span: *name_entry.span(),
+                    label: None,
+                    help: None,
+                    kind: property_found_error,
+                });
+            }
+
+            match name_entry.value().as_i64() {
+                Some(v) if v < 0 => Err(HobParseError {
+                    span: *name_entry.span(),
+                    label: None,
+                    help: None,
+                    kind: negative_error,
+                }),
+                Some(v) => Ok(v as usize),
+                None => Err(HobParseError {
+                    span: *name_entry.span(),
+                    label: None,
+                    help: None,
+                    kind: wrong_type_error,
+                }),
+            }
+        }
+
+        _ => {
+            let start_args = input.entries().first().unwrap().span().offset();
+            let end_args = input
+                .entries()
+                .last()
+                .map(|x| x.span().len() + x.span().offset())
+                .unwrap();
+
+            let span = SourceSpan::new(start_args.into(), (end_args - start_args).into());
+            Err(HobParseError {
+                span,
+                label: None,
+                help: None,
+                kind: too_many_error,
+            })
+        }
+    }
+}
+
 pub(crate) fn extract_single_string_value(
     input: &KdlNode,
     missing_error: &'static str,
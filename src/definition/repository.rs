@@ -0,0 +1,207 @@
+use crate::definition::graph::dependency_name;
+use crate::definition::parsing::{HobParseError, HobParserCompoundError, ParseDocument};
+use crate::{Document, Recipe};
+use anyhow::{bail, Context};
+use kdl::KdlDocument;
+use miette::NamedSource;
+use ring::digest::{digest, SHA256};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub const RECIPES_DIR: &str = "recipes";
+pub const RECIPE_FILE: &str = "recipe.kdl";
+
+/// A set of loaded recipes, indexed by name and by what they provide.
+///
+/// Recipe repositories are laid out as `recipes/<name>/recipe.kdl`, files used by a recipe, like
+/// its `patches/` and `files/` directories, live next to its `recipe.kdl`.
+#[derive(Debug, Default)]
+pub struct RecipeRepository {
+    pub recipes: Vec<Recipe>,
+    by_name: HashMap<String, usize>,
+    by_provides: HashMap<String, Vec<usize>>,
+}
+
+impl RecipeRepository {
+    /// Loads every recipe found in the repository at `root`.
+    ///
+    /// Files that fail to parse are left out of the repository, their errors are returned
+    /// instead, one compound error per file.
+    pub async fn load(
+        root: impl AsRef<Path>,
+    ) -> anyhow::Result<(RecipeRepository, Vec<HobParserCompoundError>)> {
+        let mut repository = RecipeRepository::default();
+        let mut errors = vec![];
+
+        for path in discover_recipes(root.as_ref()).await? {
+            if let Some(error) = repository.load_file(&path).await? {
+                errors.push(error);
+            }
+        }
+
+        Ok((repository, errors))
+    }
+
+    /// Loads all recipes in a single file into the repository, returning the parse errors of
+    /// the file if there were any.
+    pub async fn load_file(
+        &mut self,
+        path: &Path,
+    ) -> anyhow::Result<Option<HobParserCompoundError>> {
+        match parse_recipe_file(path).await? {
+            Ok(recipes) => {
+                for recipe in recipes {
+                    self.add(recipe)?;
+                }
+
+                Ok(None)
+            }
+
+            Err(error) => Ok(Some(error)),
+        }
+    }
+
+    pub fn add(&mut self, recipe: Recipe) -> anyhow::Result<usize> {
+        if let Some(existing) = self.get(&recipe.name) {
+            bail!(
+                "recipe {} is defined in both {} and {}",
+                recipe.name,
+                display_path(existing.path.as_deref()),
+                display_path(recipe.path.as_deref()),
+            );
+        }
+
+        let idx = self.recipes.len();
+        self.by_name.insert(recipe.name.clone(), idx);
+
+        let provides = recipe
+            .provides
+            .iter()
+            .map(|provide| dependency_name(provide))
+            .chain(recipe.sides.iter().map(|side| side.name.as_str()));
+
+        for provide in provides {
+            self.by_provides
+                .entry(provide.to_string())
+                .or_default()
+                .push(idx);
+        }
+
+        self.recipes.push(recipe);
+
+        Ok(idx)
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Recipe> {
+        self.position(name).map(|idx| &self.recipes[idx])
+    }
+
+    /// Returns the indices of the recipes providing `name`, either directly through their
+    /// `provides` or through one of their sides.
+    pub fn providers(&self, name: &str) -> &[usize] {
+        self.by_provides
+            .get(dependency_name(name))
+            .map_or(&[], |x| x.as_slice())
+    }
+}
+
+/// Finds all `recipes/<name>/recipe.kdl` files in the repository at `root`, sorted by name.
+pub async fn discover_recipes(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dir = tokio::fs::read_dir(root.join(RECIPES_DIR)).await?;
+    let mut found = vec![];
+
+    while let Some(entry) = dir.next_entry().await? {
+        if !entry.file_type().await?.is_dir() {
+            continue;
+        }
+
+        let path = entry.path().join(RECIPE_FILE);
+        match tokio::fs::metadata(&path).await {
+            Ok(md) if md.is_file() => found.push(path),
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    found.sort();
+
+    Ok(found)
+}
+
+/// Parses a recipe file and renders the templates of all recipes in it.
+///
+/// Both KDL syntax errors and recipe errors are reported through one compound error.
+pub async fn parse_recipe_file(
+    path: &Path,
+) -> anyhow::Result<Result<Vec<Recipe>, HobParserCompoundError>> {
+    let source = tokio::fs::read_to_string(path).await?;
+    let compound = |source: String, errors| HobParserCompoundError {
+        source_code: NamedSource::new(path.display().to_string(), source),
+        errors,
+    };
+
+    let kdl_document = match source.parse::<KdlDocument>() {
+        Ok(doc) => doc,
+        Err(e) => {
+            let error = HobParseError {
+                span: e.span,
+                label: e.label,
+                help: Some(
+                    e.help
+                        .map(ToString::to_string)
+                        .unwrap_or_else(|| e.kind.to_string()),
+                ),
+                kind: "invalid kdl document",
+            };
+
+            return Ok(Err(compound(source, vec![error])));
+        }
+    };
+
+    let (document, errors) = Document::parse_document_with_errors(&kdl_document);
+    if !errors.is_empty() {
+        return Ok(Err(compound(source, errors)));
+    }
+
+    let mut recipes = document.map_or(vec![], |x| x.recipes);
+    for recipe in &mut recipes {
+        recipe.path = Some(path.to_path_buf());
+        recipe.render_templates();
+        digest_local_artifacts(recipe).await?;
+    }
+
+    Ok(Ok(recipes))
+}
+
+/// Hashes the contents of the local files of a recipe, so they become part of their cache key.
+async fn digest_local_artifacts(recipe: &mut Recipe) -> anyhow::Result<()> {
+    let dir = recipe
+        .directory()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    for artifact in &mut recipe.artifacts {
+        let local = match artifact.source.local_mut() {
+            Some(local) => local,
+            None => continue,
+        };
+
+        let data = tokio::fs::read(dir.join(&local.path))
+            .await
+            .with_context(|| format!("can't read {} of recipe {}", local.path, recipe.name))?;
+
+        local.digest = Some(digest(&SHA256, &data).as_ref().try_into().unwrap());
+    }
+
+    Ok(())
+}
+
+fn display_path(path: Option<&Path>) -> String {
+    path.map_or_else(|| "[memory]".to_string(), |p| p.display().to_string())
+}
@@ -0,0 +1,65 @@
+use anyhow::{anyhow, Context};
+use kdl::{KdlDocument, KdlEntry, KdlNode};
+use std::path::Path;
+
+const INDENT: &str = "    ";
+
+/// Adds a `sha256` node to artifacts of the recipe named `recipe` in the file at `path`.
+///
+/// `checksums` holds the hex encoded digests by index of the artifact, counting through all
+/// `artifacts` blocks of the recipe in order. Everything else in the file is kept as it was.
+pub async fn add_checksums(
+    path: &Path,
+    recipe: &str,
+    checksums: &[(usize, String)],
+) -> anyhow::Result<()> {
+    let source = tokio::fs::read_to_string(path).await?;
+    let mut document: KdlDocument = source
+        .parse()
+        .map_err(|e| anyhow!("{:?}", miette::Error::new(e)))?;
+
+    let recipe_node = document
+        .nodes_mut()
+        .iter_mut()
+        .find(|node| {
+            node.name().value() == "recipe"
+                && node.entries().first().and_then(|x| x.value().as_string()) == Some(recipe)
+        })
+        .with_context(|| format!("recipe {} not found in {}", recipe, path.display()))?;
+
+    let recipe_indent = recipe_node.leading().unwrap_or("").trim_start_matches('\n');
+    let artifact_indent = format!("{}{}{}", recipe_indent, INDENT, INDENT);
+
+    let mut artifacts: Vec<&mut KdlNode> = recipe_node
+        .children_mut()
+        .iter_mut()
+        .flat_map(|children| children.nodes_mut().iter_mut())
+        .filter(|node| node.name().value() == "artifacts")
+        .flat_map(|node| node.children_mut().iter_mut())
+        .flat_map(|children| children.nodes_mut().iter_mut())
+        .collect();
+
+    for (idx, checksum) in checksums {
+        let artifact = artifacts
+            .get_mut(*idx)
+            .with_context(|| format!("artifact {} of {} not found", idx, recipe))?;
+
+        let children = artifact.ensure_children();
+        let indent = children
+            .nodes()
+            .first()
+            .and_then(|x| x.leading())
+            .map(|x| x.trim_start_matches('\n').to_string())
+            .unwrap_or_else(|| format!("{}{}", artifact_indent, INDENT));
+
+        let mut node = KdlNode::new("sha256");
+        node.push(KdlEntry::new(checksum.as_str()));
+        node.set_leading(indent);
+        node.set_trailing("\n");
+        children.nodes_mut().push(node);
+    }
+
+    tokio::fs::write(path, document.to_string()).await?;
+
+    Ok(())
+}
@@ -0,0 +1,24 @@
+use crate::definition::{Recipe, RecipeTemplate};
+use handlebars::Handlebars;
+use hob_utils::{ObjectTraversal, ObjectWalker};
+
+pub struct TemplateReplace<'a> {
+    engine: Handlebars<'a>,
+    vars: RecipeTemplate,
+}
+
+impl ObjectWalker for TemplateReplace<'_> {
+    fn enter_string(&mut self, value: &mut String) {
+        *value = self.engine.render_template(value, &self.vars).unwrap();
+    }
+}
+
+impl Recipe {
+    pub fn render_templates(&mut self) {
+        let vars = self.template_vars();
+        self.traverse(&mut TemplateReplace {
+            engine: Default::default(),
+            vars,
+        })
+    }
+}
@@ -0,0 +1,489 @@
+//! Unpacking of cpio archives and of the payloads of deb and rpm packages.
+
+use crate::engine::extractor::{
+    contained_path, decompress_sniffed, remove_if_exists, set_mode, ArchiveRoot, S_IFLNK, S_IFMT,
+};
+use anyhow::{anyhow, bail};
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// Longest name or symlink target read from a cpio header, a corrupt header could ask for
+/// gigabytes otherwise.
+const PATH_MAX: u64 = 4096;
+
+const CPIO_TRAILER: &str = "TRAILER!!!";
+pub const AR_MAGIC: &[u8] = b"!<arch>\n";
+pub const DEB_FIRST_MEMBER: &[u8] = b"debian-binary";
+pub const RPM_LEAD_MAGIC: &[u8] = &[0xed, 0xab, 0xee, 0xdb];
+const RPM_HEADER_MAGIC: &[u8] = &[0x8e, 0xad, 0xe8];
+
+/// Magic of the newc, newc with checksums and odc cpio formats.
+pub const CPIO_MAGIC: &[&[u8]] = &[b"070701", b"070702", b"070707"];
+
+struct CpioHeader {
+    /// Inode, combined with the device for odc, which doesn't number them per archive.
+    ino: u64,
+    mode: u32,
+    nlink: u64,
+    file_size: u64,
+    name_size: u64,
+    header_size: u64,
+    /// Names and data are padded to this, 4 for newc and 1 for odc.
+    align: u64,
+}
+
+/// Unpacks a cpio archive in the newc or odc format into `dest`.
+///
+/// Device nodes, fifos and sockets are skipped, they have no place in sources.
+pub async fn unpack_cpio<R: AsyncRead + Unpin>(mut read: R, dest: &Path) -> anyhow::Result<()> {
+    // newc only stores the data of hard linked files with their last link
+    let mut links: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let mut root = ArchiveRoot::new(dest).await?;
+
+    loop {
+        let header = read_cpio_header(&mut read).await?;
+        if header.name_size > PATH_MAX {
+            bail!("cpio archive has a corrupt header");
+        }
+
+        let mut name = vec![0; header.name_size as usize];
+        read.read_exact(&mut name).await?;
+        skip(
+            &mut read,
+            padding(header.header_size + header.name_size, header.align),
+        )
+        .await?;
+
+        let name = String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(&name)).into_owned();
+        if name == CPIO_TRAILER {
+            break;
+        }
+
+        let relative = contained_path(Path::new(&name)).ok_or_else(|| {
+            anyhow!(
+                "cpio entry {} would be extracted outside of the sources",
+                name
+            )
+        })?;
+
+        let file_type = header.mode & S_IFMT;
+
+        if relative.as_os_str().is_empty() {
+            skip(&mut read, header.file_size).await?;
+        } else if file_type == S_IFDIR {
+            let target = root.create_dir(&relative).await?;
+            // keep directories writable, or the entries inside couldn't be extracted
+            set_mode(&target, header.mode | 0o700).await?;
+        } else if file_type == S_IFLNK {
+            if header.file_size > PATH_MAX {
+                bail!("cpio archive has a corrupt header");
+            }
+
+            let mut link = vec![0; header.file_size as usize];
+            read.read_exact(&mut link).await?;
+            let link = PathBuf::from(String::from_utf8(link)?);
+
+            root.symlink(&relative, &link).await?;
+        } else if file_type == S_IFREG {
+            if header.file_size == 0 && header.nlink > 1 {
+                links.entry(header.ino).or_default().push(relative);
+            } else {
+                let target = root.prepare(&relative).await?;
+                write_file(&mut read, &target, header.file_size).await?;
+                set_mode(&target, header.mode).await?;
+
+                // the links are checked now, the archive may have planted symlinks since
+                for link in links.remove(&header.ino).unwrap_or_default() {
+                    let link = root.prepare(&link).await?;
+                    remove_if_exists(&link).await?;
+                    tokio::fs::hard_link(&target, &link).await?;
+                }
+            }
+        } else {
+            skip(&mut read, header.file_size).await?;
+        }
+
+        skip(&mut read, padding(header.file_size, header.align)).await?;
+    }
+
+    // links of which no data was stored are empty files
+    for path in links.into_values().flatten() {
+        File::create(root.prepare(&path).await?).await?;
+    }
+
+    Ok(())
+}
+
+async fn read_cpio_header<R: AsyncRead + Unpin>(read: &mut R) -> anyhow::Result<CpioHeader> {
+    let mut magic = [0; 6];
+    read.read_exact(&mut magic).await?;
+
+    match &magic {
+        b"070701" | b"070702" => {
+            let mut fields = [0; 104];
+            read.read_exact(&mut fields).await?;
+            let field = |idx: usize| parse_number(&fields[idx * 8..idx * 8 + 8], 16);
+
+            Ok(CpioHeader {
+                ino: field(0)?,
+                mode: field(1)? as u32,
+                nlink: field(4)?,
+                file_size: field(6)?,
+                name_size: field(11)?,
+                header_size: 110,
+                align: 4,
+            })
+        }
+
+        b"070707" => {
+            let mut fields = [0; 70];
+            read.read_exact(&mut fields).await?;
+            let field = |start: usize, end: usize| parse_number(&fields[start..end], 8);
+
+            Ok(CpioHeader {
+                ino: (field(0, 6)? << 32) | field(6, 12)?,
+                mode: field(12, 18)? as u32,
+                nlink: field(30, 36)?,
+                name_size: field(53, 59)?,
+                file_size: field(59, 70)?,
+                header_size: 76,
+                align: 1,
+            })
+        }
+
+        _ => bail!("not a cpio archive, only the newc and odc formats are supported"),
+    }
+}
+
+/// Unpacks the `data.tar` member of a deb package into `dest`.
+pub async fn unpack_deb<R: AsyncRead + Unpin>(mut read: R, dest: &Path) -> anyhow::Result<()> {
+    let mut magic = [0; 8];
+    read.read_exact(&mut magic).await?;
+    if magic != AR_MAGIC {
+        bail!("not a deb package");
+    }
+
+    loop {
+        let mut header = [0; 60];
+        match read.read_exact(&mut header).await {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                bail!("deb package has no data.tar member")
+            }
+            res => res?,
+        };
+
+        let name = String::from_utf8_lossy(&header[..16]);
+        let size = parse_number(&header[48..58], 10)?;
+
+        if name.starts_with("data.tar") {
+            let member = decompress_sniffed(BufReader::new((&mut read).take(size))).await?;
+            tokio_tar::Archive::new(member).unpack(dest).await?;
+
+            return Ok(());
+        }
+
+        skip(&mut read, size + padding(size, 2)).await?;
+    }
+}
+
+/// Unpacks the cpio payload of an rpm package into `dest`.
+pub async fn unpack_rpm<R: AsyncRead + Unpin>(mut read: R, dest: &Path) -> anyhow::Result<()> {
+    let mut lead = [0; 96];
+    read.read_exact(&mut lead).await?;
+    if !lead.starts_with(RPM_LEAD_MAGIC) {
+        bail!("not an rpm package");
+    }
+
+    // the signature header is padded to 8 bytes, the main header isn't
+    let size = skip_rpm_header(&mut read).await?;
+    skip(&mut read, padding(size, 8)).await?;
+    skip_rpm_header(&mut read).await?;
+
+    let payload = decompress_sniffed(BufReader::new(read)).await?;
+    unpack_cpio(payload, dest).await
+}
+
+/// Skips an rpm header structure, returning its size.
+async fn skip_rpm_header<R: AsyncRead + Unpin>(read: &mut R) -> anyhow::Result<u64> {
+    let mut intro = [0; 16];
+    read.read_exact(&mut intro).await?;
+    if !intro.starts_with(RPM_HEADER_MAGIC) {
+        bail!("rpm package has a corrupt header");
+    }
+
+    let entries = u32::from_be_bytes(intro[8..12].try_into().unwrap()) as u64;
+    let data = u32::from_be_bytes(intro[12..16].try_into().unwrap()) as u64;
+    let size = entries * 16 + data;
+    skip(read, size).await?;
+
+    Ok(16 + size)
+}
+
+async fn write_file<R: AsyncRead + Unpin>(
+    read: &mut R,
+    target: &Path,
+    size: u64,
+) -> anyhow::Result<()> {
+    let mut out = File::create(target).await?;
+    let written = tokio::io::copy(&mut read.take(size), &mut out).await?;
+    if written != size {
+        bail!("archive ended in the middle of {}", target.display());
+    }
+
+    Ok(())
+}
+
+async fn skip<R: AsyncRead + Unpin>(read: &mut R, size: u64) -> anyhow::Result<()> {
+    let skipped = tokio::io::copy(&mut read.take(size), &mut tokio::io::sink()).await?;
+    if skipped != size {
+        bail!("archive ended unexpectedly");
+    }
+
+    Ok(())
+}
+
+fn padding(size: u64, align: u64) -> u64 {
+    (align - size % align) % align
+}
+
+/// Parses a number in a header field, which may be padded with spaces.
+fn parse_number(field: &[u8], radix: u32) -> anyhow::Result<u64> {
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|x| u64::from_str_radix(x.trim(), radix).ok())
+        .ok_or_else(|| anyhow!("archive has a corrupt header"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::extractor::tests::scratch_dir;
+    use std::os::unix::fs::MetadataExt;
+
+    fn newc_entry(out: &mut Vec<u8>, ino: u64, mode: u32, nlink: u64, name: &str, data: &[u8]) {
+        let fields = [
+            ino,
+            mode as u64,
+            0,
+            0,
+            nlink,
+            0,
+            data.len() as u64,
+            0,
+            0,
+            0,
+            0,
+            name.len() as u64 + 1,
+            0,
+        ];
+
+        out.extend_from_slice(b"070701");
+        for field in fields {
+            out.extend_from_slice(format!("{:08x}", field).as_bytes());
+        }
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.resize(out.len() + padding(out.len() as u64, 4) as usize, 0);
+        out.extend_from_slice(data);
+        out.resize(out.len() + padding(out.len() as u64, 4) as usize, 0);
+    }
+
+    fn odc_entry(out: &mut Vec<u8>, ino: u64, mode: u32, nlink: u64, name: &str, data: &[u8]) {
+        out.extend_from_slice(b"070707");
+        out.extend_from_slice(
+            format!(
+                "{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:011o}{:06o}{:011o}",
+                0,
+                ino,
+                mode,
+                0,
+                0,
+                nlink,
+                0,
+                0,
+                name.len() + 1,
+                data.len()
+            )
+            .as_bytes(),
+        );
+        out.extend_from_slice(name.as_bytes());
+        out.push(0);
+        out.extend_from_slice(data);
+    }
+
+    fn ar_member(out: &mut Vec<u8>, name: &str, data: &[u8]) {
+        out.extend_from_slice(
+            format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                name,
+                0,
+                0,
+                0,
+                100644,
+                data.len()
+            )
+            .as_bytes(),
+        );
+        out.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            out.push(b'\n');
+        }
+    }
+
+    fn rpm_header(out: &mut Vec<u8>, data: &[u8]) {
+        out.extend_from_slice(&[0x8e, 0xad, 0xe8, 0x01, 0, 0, 0, 0]);
+        out.extend_from_slice(&1u32.to_be_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&[0; 16]);
+        out.extend_from_slice(data);
+    }
+
+    fn newc_with_hard_link() -> Vec<u8> {
+        let mut cpio = vec![];
+        newc_entry(&mut cpio, 1, S_IFDIR | 0o755, 2, "dir", b"");
+        newc_entry(&mut cpio, 2, S_IFREG | 0o644, 2, "dir/first", b"");
+        newc_entry(&mut cpio, 2, S_IFREG | 0o644, 2, "dir/second", b"linked");
+        newc_entry(&mut cpio, 3, S_IFLNK | 0o777, 1, "link", b"dir/second");
+        newc_entry(&mut cpio, 0, 0, 1, CPIO_TRAILER, b"");
+
+        cpio
+    }
+
+    #[tokio::test]
+    async fn newc_hard_links() {
+        let dest = scratch_dir("cpio-newc").await;
+        unpack_cpio(&newc_with_hard_link()[..], &dest)
+            .await
+            .unwrap();
+
+        let first = tokio::fs::metadata(dest.join("dir/first")).await.unwrap();
+        let second = tokio::fs::metadata(dest.join("dir/second")).await.unwrap();
+        assert_eq!(first.ino(), second.ino());
+        assert_eq!(
+            tokio::fs::read(dest.join("dir/first")).await.unwrap(),
+            b"linked"
+        );
+        assert_eq!(
+            tokio::fs::read_link(dest.join("link")).await.unwrap(),
+            Path::new("dir/second")
+        );
+
+        tokio::fs::remove_dir_all(&dest).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn odc_hard_links() {
+        let mut cpio = vec![];
+        odc_entry(&mut cpio, 7, S_IFREG | 0o755, 2, "./a", b"odd");
+        odc_entry(&mut cpio, 7, S_IFREG | 0o755, 2, "./b", b"odd");
+        odc_entry(&mut cpio, 0, 0, 1, CPIO_TRAILER, b"");
+
+        let dest = scratch_dir("cpio-odc").await;
+        unpack_cpio(&cpio[..], &dest).await.unwrap();
+
+        for name in ["a", "b"] {
+            let path = dest.join(name);
+            assert_eq!(tokio::fs::read(&path).await.unwrap(), b"odd");
+            assert_eq!(
+                tokio::fs::metadata(&path).await.unwrap().mode() & 0o777,
+                0o755
+            );
+        }
+
+        tokio::fs::remove_dir_all(&dest).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cpio_escapes_are_refused() {
+        let dir = scratch_dir("cpio-escape").await;
+        let dest = dir.join("sources");
+        tokio::fs::create_dir_all(&dest).await.unwrap();
+
+        let mut cpio = vec![];
+        newc_entry(&mut cpio, 1, S_IFREG | 0o644, 1, "../escaped", b"x");
+        newc_entry(&mut cpio, 0, 0, 1, CPIO_TRAILER, b"");
+        assert!(unpack_cpio(&cpio[..], &dest).await.is_err());
+
+        // `l` passes the check of its name, but points at the parent of `dest`
+        let mut cpio = vec![];
+        newc_entry(&mut cpio, 1, S_IFLNK | 0o777, 1, "d", b".");
+        newc_entry(&mut cpio, 2, S_IFLNK | 0o777, 1, "l", b"d/..");
+        newc_entry(&mut cpio, 3, S_IFREG | 0o644, 1, "l/escaped", b"x");
+        newc_entry(&mut cpio, 0, 0, 1, CPIO_TRAILER, b"");
+        assert!(unpack_cpio(&cpio[..], &dest).await.is_err());
+
+        assert!(tokio::fs::metadata(dir.join("escaped")).await.is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cpio_huge_names_are_refused() {
+        let mut cpio = vec![];
+        newc_entry(&mut cpio, 1, S_IFREG | 0o644, 1, "a", b"");
+        // claim a name of 4GiB
+        cpio[6 + 11 * 8..6 + 12 * 8].copy_from_slice(b"ffffffff");
+
+        let dest = scratch_dir("cpio-huge").await;
+        let err = unpack_cpio(&cpio[..], &dest).await.unwrap_err();
+        assert!(err.to_string().contains("corrupt header"));
+
+        tokio::fs::remove_dir_all(&dest).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deb_data_member() {
+        let mut tar = tokio_tar::Builder::new(vec![]);
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, "usr/share/doc/hob", &b"docs"[..])
+            .await
+            .unwrap();
+        let tar = tar.into_inner().await.unwrap();
+
+        let mut deb = AR_MAGIC.to_vec();
+        ar_member(&mut deb, "debian-binary", b"2.0\n");
+        // odd sized, so the next member starts after a padding byte
+        ar_member(&mut deb, "control.tar", b"abc");
+        ar_member(&mut deb, "data.tar", &tar);
+
+        let dest = scratch_dir("deb").await;
+        unpack_deb(&deb[..], &dest).await.unwrap();
+        assert_eq!(
+            tokio::fs::read(dest.join("usr/share/doc/hob"))
+                .await
+                .unwrap(),
+            b"docs"
+        );
+
+        tokio::fs::remove_dir_all(&dest).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rpm_payload() {
+        let mut rpm = RPM_LEAD_MAGIC.to_vec();
+        rpm.resize(96, 0);
+        // 37 bytes, padded to 40
+        rpm_header(&mut rpm, b"12345");
+        rpm.extend_from_slice(&[0; 3]);
+        rpm_header(&mut rpm, b"123");
+        rpm.extend_from_slice(&newc_with_hard_link());
+
+        let dest = scratch_dir("rpm").await;
+        unpack_rpm(&rpm[..], &dest).await.unwrap();
+        assert_eq!(
+            tokio::fs::read(dest.join("dir/second")).await.unwrap(),
+            b"linked"
+        );
+
+        tokio::fs::remove_dir_all(&dest).await.unwrap();
+    }
+}
@@ -9,8 +9,22 @@ use std::time::SystemTime;
 pub struct BuildState<'a> {
     pub build_time: SystemTime,
     pub recipe: &'a Recipe,
+    pub cpus: usize,
     pub stage: Stage,
     pub artifacts: Vec<FetchedArtifact<'a>>,
     pub elf_headers: HashMap<PathBuf, ElfHeader>,
     pub archives: Vec<PathBuf>,
+    /// Files found in the sources after extracting, sorted by path.
+    pub source_manifest: Vec<SourceFile>,
+}
+
+/// A file in the extracted sources of a recipe.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    /// Path relative to the source directory of the build.
+    pub path: PathBuf,
+    pub size: u64,
+    pub mode: u32,
+    /// Sha256 of the contents, `None` for symlinks.
+    pub sha256: Option<[u8; 32]>,
 }
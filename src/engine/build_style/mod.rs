@@ -33,6 +33,20 @@ pub const TYPES: &[BuildStyle] = &[
         install: &[Action::MakeInstall],
         check: &[],
     },
+    BuildStyle {
+        style: BuildStyleType::Cmake,
+        configure: &[Action::CmakeConfigure],
+        build: &[Action::CmakeBuild],
+        install: &[Action::CmakeInstall],
+        check: &[Action::CmakeCheck],
+    },
+    BuildStyle {
+        style: BuildStyleType::Meson,
+        configure: &[Action::MesonSetup],
+        build: &[Action::MesonCompile],
+        install: &[Action::MesonInstall],
+        check: &[Action::MesonTest],
+    },
 ];
 
 pub fn get_build_style(style: BuildStyleType) -> &'static BuildStyle {
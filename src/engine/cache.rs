@@ -0,0 +1,261 @@
+use crate::definition::{ArtifactSource, ChecksumAlgorithm};
+use crate::engine::git::GitMirror;
+use crate::engine::Engine;
+use crate::Recipe;
+use hex::ToHex;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// A file or git mirror in the artifact cache.
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub kind: CacheEntryKind,
+    /// Size in bytes, including everything inside for git mirrors.
+    pub size: u64,
+    pub modified: SystemTime,
+    /// Recipe and artifact indices of every artifact this entry was fetched for.
+    pub owners: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum CacheEntryKind {
+    Artifact,
+    GitMirror,
+    Signature,
+    /// An interrupted download or clone.
+    Partial,
+}
+
+impl CacheEntryKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheEntryKind::Artifact => "artifact",
+            CacheEntryKind::GitMirror => "git mirror",
+            CacheEntryKind::Signature => "signature",
+            CacheEntryKind::Partial => "partial",
+        }
+    }
+}
+
+impl CacheEntry {
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    pub fn is_referenced(&self) -> bool {
+        !self.owners.is_empty()
+    }
+
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.modified)
+            .unwrap_or_default()
+    }
+}
+
+impl Engine {
+    /// Lists everything in the cache, mapping entries back to the artifacts of `recipes` they
+    /// were fetched for by the [`crate::definition::Artifact::hash_id`] prefixing their name.
+    pub async fn cache_entries(&self, recipes: &[Recipe]) -> anyhow::Result<Vec<CacheEntry>> {
+        let mut artifacts: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        for (recipe_idx, recipe) in recipes.iter().enumerate() {
+            for (artifact_idx, artifact) in recipe.artifacts.iter().enumerate() {
+                artifacts
+                    .entry(artifact.hash_id().encode_hex::<String>())
+                    .or_default()
+                    .push((recipe_idx, artifact_idx));
+            }
+        }
+
+        let mut dir = match tokio::fs::read_dir(self.settings.cache_path()).await {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = vec![];
+        while let Some(item) = dir.next_entry().await? {
+            let path = item.path();
+            let metadata = item.metadata().await?;
+            let name = item.file_name().to_string_lossy().into_owned();
+
+            let owners = name
+                .split_once('-')
+                .and_then(|(hash, _)| artifacts.get(hash))
+                .cloned()
+                .unwrap_or_default();
+
+            let kind = if name.ends_with(".part") {
+                CacheEntryKind::Partial
+            } else if metadata.is_dir() {
+                CacheEntryKind::GitMirror
+            } else if self.is_signature(recipes, &owners, &name) {
+                CacheEntryKind::Signature
+            } else {
+                CacheEntryKind::Artifact
+            };
+
+            let size = if metadata.is_dir() {
+                dir_size(&path).await?
+            } else {
+                metadata.len()
+            };
+
+            entries.push(CacheEntry {
+                path,
+                kind,
+                size,
+                modified: metadata.modified()?,
+                owners,
+            });
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(entries)
+    }
+
+    /// Checks a cached artifact against the recipe it belongs to.
+    ///
+    /// Returns `None` for entries that can't be verified, like signatures, partial downloads
+    /// and anything no recipe refers to, otherwise whether the entry is intact, with the reason
+    /// why not.
+    pub async fn verify_cache_entry(
+        &self,
+        recipes: &[Recipe],
+        entry: &CacheEntry,
+    ) -> anyhow::Result<Option<Result<(), String>>> {
+        let (recipe_idx, artifact_idx) = match entry.owners.first() {
+            Some(owner) => *owner,
+            None => return Ok(None),
+        };
+        let artifact = &recipes[recipe_idx].artifacts[artifact_idx];
+
+        match (entry.kind, &artifact.source) {
+            (CacheEntryKind::GitMirror, ArtifactSource::Git(_)) => {
+                // every reference of the repository is resolved in the same mirror
+                let mirror = GitMirror::new(&entry.path);
+                let mut missing = vec![];
+                for (recipe_idx, artifact_idx) in &entry.owners {
+                    if let ArtifactSource::Git(git) =
+                        &recipes[*recipe_idx].artifacts[*artifact_idx].source
+                    {
+                        if mirror.resolve(git).await?.is_none() {
+                            missing.push(format!(
+                                "{} {}",
+                                git.reference.kind(),
+                                git.reference.name()
+                            ));
+                        }
+                    }
+                }
+
+                Ok(Some(if missing.is_empty() {
+                    Ok(())
+                } else {
+                    Err(format!("{} missing from the mirror", missing.join(", ")))
+                }))
+            }
+
+            (CacheEntryKind::Artifact, _) => {
+                if let Err(hashes) = self
+                    .fetcher
+                    .verify_file(&entry.path, &artifact.verification)
+                    .await?
+                {
+                    let reasons: Vec<_> = hashes.iter().map(|x| x.to_string()).collect();
+                    return Ok(Some(Err(reasons.join(", "))));
+                }
+
+                // local files are pinned by the digest taken when their recipe was loaded
+                if let Some(expected) = artifact.source.local().and_then(|x| x.digest) {
+                    let digests = self
+                        .fetcher
+                        .compute_digests(&entry.path, &[ChecksumAlgorithm::Sha256])
+                        .await?;
+
+                    if digests.iter().any(|(_, found)| found.as_ref() != expected) {
+                        return Ok(Some(Err(
+                            "file changed in the recipe repository since it was cached".to_string(),
+                        )));
+                    }
+                }
+
+                Ok(Some(Ok(())))
+            }
+
+            _ => Ok(None),
+        }
+    }
+
+    /// Removes the cache entries that no recipe in `recipes` refers to, and when `older_than`
+    /// is given only those of them that were fetched longer ago than that, returning what was
+    /// removed. Entries that are still referenced, including git mirrors, are always kept.
+    ///
+    /// With `dry_run` nothing is removed, only returned.
+    pub async fn collect_cache_garbage(
+        &self,
+        recipes: &[Recipe],
+        older_than: Option<Duration>,
+        dry_run: bool,
+    ) -> anyhow::Result<Vec<CacheEntry>> {
+        let mut removed = vec![];
+        for entry in self.cache_entries(recipes).await? {
+            let expired = older_than.is_none_or(|max| entry.age() > max);
+            if entry.is_referenced() || !expired {
+                continue;
+            }
+
+            if !dry_run {
+                if entry.path.is_dir() {
+                    tokio::fs::remove_dir_all(&entry.path).await?;
+                } else {
+                    tokio::fs::remove_file(&entry.path).await?;
+                }
+            }
+
+            removed.push(entry);
+        }
+
+        Ok(removed)
+    }
+
+    /// Whether `name` is the detached signature of one of the artifacts it belongs to.
+    fn is_signature(&self, recipes: &[Recipe], owners: &[(usize, usize)], name: &str) -> bool {
+        owners.iter().any(|(recipe_idx, artifact_idx)| {
+            let artifact = &recipes[*recipe_idx].artifacts[*artifact_idx];
+            let path = self.fetcher.create_artifact_path(artifact);
+
+            artifact.verification.signature.is_some()
+                && path.file_name().is_some_and(|x| {
+                    name.len() > x.len() && name.starts_with(&*x.to_string_lossy())
+                })
+        })
+    }
+}
+
+async fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    let mut pending = vec![path.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else {
+                size += metadata.len();
+            }
+        }
+    }
+
+    Ok(size)
+}
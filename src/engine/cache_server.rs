@@ -0,0 +1,161 @@
+use crate::engine::{Engine, EngineSettings};
+use hyper::body::Bytes;
+use hyper::header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, RANGE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::io::{ErrorKind, SeekFrom};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+impl Engine {
+    /// Serves the artifact cache over http, with every artifact at `/<hash>-<name>` like it is
+    /// laid out on disk, so other instances can use it as their `cache-server`.
+    ///
+    /// Only complete files are served, git mirrors and interrupted downloads are not.
+    pub async fn serve_cache(&self, addr: SocketAddr) -> anyhow::Result<()> {
+        let settings = self.settings.clone();
+        let make_service = make_service_fn(move |_| {
+            let settings = settings.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let settings = settings.clone();
+                    async move { Ok::<_, Infallible>(handle(&settings, req).await) }
+                }))
+            }
+        });
+
+        let server = Server::try_bind(&addr)?.serve(make_service);
+        println!(
+            "serving {} on http://{}",
+            self.settings.cache_path().display(),
+            server.local_addr()
+        );
+
+        server.await?;
+
+        Ok(())
+    }
+}
+
+async fn handle(settings: &EngineSettings, req: Request<Body>) -> Response<Body> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return status(StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    let path = match cache_file(settings, req.uri().path()) {
+        Some(path) => path,
+        None => return status(StatusCode::NOT_FOUND),
+    };
+
+    let range = req
+        .headers()
+        .get(RANGE)
+        .and_then(|x| x.to_str().ok())
+        .and_then(parse_range);
+
+    match serve_file(path, range, req.method() == Method::HEAD).await {
+        Ok(resp) => resp,
+        Err(e) if e.kind() == ErrorKind::NotFound => status(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("failed to serve {}: {}", req.uri().path(), e);
+            status(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Maps a request path to a file in the cache, refusing anything that isn't a plain file name.
+fn cache_file(settings: &EngineSettings, path: &str) -> Option<PathBuf> {
+    let name = path.strip_prefix('/')?;
+    if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+        return None;
+    }
+
+    if name.ends_with(".part") {
+        return None;
+    }
+
+    Some(settings.cache_path().join(name))
+}
+
+/// Parses a `bytes=<start>-` range, the only kind hob asks for when resuming a download.
+fn parse_range(value: &str) -> Option<u64> {
+    value
+        .strip_prefix("bytes=")?
+        .strip_suffix('-')?
+        .parse()
+        .ok()
+}
+
+async fn serve_file(
+    path: PathBuf,
+    range: Option<u64>,
+    head: bool,
+) -> std::io::Result<Response<Body>> {
+    let mut file = tokio::fs::File::open(&path).await?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(ErrorKind::NotFound.into());
+    }
+
+    let len = metadata.len();
+    let start = range.unwrap_or(0);
+    if start > len || (range.is_some() && start == len) {
+        return Ok(Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(CONTENT_RANGE, format!("bytes */{}", len))
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let mut resp = Response::builder()
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CONTENT_LENGTH, len - start);
+
+    if range.is_some() {
+        resp = resp.status(StatusCode::PARTIAL_CONTENT).header(
+            CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, len - 1, len),
+        );
+    }
+
+    if head {
+        return Ok(resp.body(Body::empty()).unwrap());
+    }
+
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = match file.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(_) => {
+                    sender.abort();
+                    break;
+                }
+            };
+
+            // the client went away
+            if sender
+                .send_data(Bytes::copy_from_slice(&buffer[..read]))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    Ok(resp.body(body).unwrap())
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+}
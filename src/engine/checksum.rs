@@ -0,0 +1,39 @@
+use crate::definition::{ArtifactSource, ChecksumAlgorithm};
+use crate::engine::Engine;
+use crate::Recipe;
+
+impl Engine {
+    /// Fetches the artifacts of `recipe` that have no sha256 checksum yet, returning the hex
+    /// encoded sha256 of each by the index of the artifact.
+    pub async fn missing_checksums(&self, recipe: &Recipe) -> anyhow::Result<Vec<(usize, String)>> {
+        let mut checksums = vec![];
+
+        for (idx, artifact) in recipe.artifacts.iter().enumerate() {
+            let has_sha256 = artifact
+                .verification
+                .checksums
+                .iter()
+                .any(|sum| sum.algorithm == ChecksumAlgorithm::Sha256);
+
+            if has_sha256 || !matches!(artifact.source, ArtifactSource::Fetch(_)) {
+                continue;
+            }
+
+            println!("[{}] fetching {}", recipe.name, artifact.file_name());
+            let fetched = self
+                .fetcher
+                .fetch_allow_unverified(recipe, artifact)
+                .await?;
+            let digests = self
+                .fetcher
+                .compute_digests(&fetched.path, &[ChecksumAlgorithm::Sha256])
+                .await?;
+
+            for (_, digest) in digests {
+                checksums.push((idx, hex::encode(digest)));
+            }
+        }
+
+        Ok(checksums)
+    }
+}
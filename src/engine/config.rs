@@ -0,0 +1,306 @@
+use crate::definition::parsing::{HobParseError, ParseDocument};
+use crate::engine::packager::PackagerType;
+use crate::engine::{ChrootMethod, EngineSettings};
+use crate::{parse_bool_into, parse_string_into, parse_string_list_into, parse_usize_into};
+use anyhow::{anyhow, bail, Context};
+use kdl::{KdlDocument, KdlNode};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const SYSTEM_CONFIG: &str = "/etc/hob/config.kdl";
+pub const PROJECT_CONFIG: &str = ".hob/config.kdl";
+
+/// Partial engine configuration, as read from a single configuration file or the environment.
+///
+/// Unset values fall through to the next configuration level, and eventually to the defaults
+/// in [`EngineConfig::into_settings`].
+#[derive(Debug, Default, Clone)]
+pub struct EngineConfig {
+    pub cache_path: Option<PathBuf>,
+    pub root_path: Option<PathBuf>,
+    pub source_path: Option<PathBuf>,
+    pub dest_path: Option<PathBuf>,
+    pub package_path: Option<PathBuf>,
+    pub chroot_method: Option<ChrootMethod>,
+    pub packager: Option<PackagerType>,
+    pub jobs: Option<usize>,
+    pub cpus: Option<usize>,
+    pub strict: Option<bool>,
+    pub offline: Option<bool>,
+    pub mirrors: Option<Vec<String>>,
+    pub cache_server: Option<String>,
+    pub fetch_retries: Option<usize>,
+    pub fetch_timeout: Option<usize>,
+}
+
+impl EngineConfig {
+    /// Loads the system, user and project configuration, in that order, followed by `extra` and
+    /// finally the `HOB_*` environment variables.
+    pub async fn load(extra: Option<&Path>) -> anyhow::Result<EngineConfig> {
+        let mut paths = vec![PathBuf::from(SYSTEM_CONFIG)];
+        paths.extend(user_config_path());
+        paths.push(PathBuf::from(PROJECT_CONFIG));
+
+        let mut config = EngineConfig::default();
+        for path in paths {
+            if let Some(file_config) = Self::load_file(&path).await? {
+                config.merge(file_config);
+            }
+        }
+
+        if let Some(path) = extra {
+            match Self::load_file(path).await? {
+                Some(file_config) => config.merge(file_config),
+                None => bail!("configuration file {} not found", path.display()),
+            }
+        }
+
+        config.apply_env()?;
+
+        Ok(config)
+    }
+
+    pub async fn load_file(path: &Path) -> anyhow::Result<Option<EngineConfig>> {
+        let source = match tokio::fs::read_to_string(path).await {
+            Ok(source) => source,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let document: KdlDocument = source
+            .parse()
+            .map_err(|e| anyhow!("{:?}", miette::Error::new(e)))?;
+
+        let file_name = path.display().to_string();
+        EngineConfig::parse_document_strict(&document, &source, Some(&file_name))
+            .map(Some)
+            .map_err(|e| anyhow!("{:?}", e))
+    }
+
+    pub fn merge(&mut self, other: EngineConfig) {
+        self.cache_path = other.cache_path.or(self.cache_path.take());
+        self.root_path = other.root_path.or(self.root_path.take());
+        self.source_path = other.source_path.or(self.source_path.take());
+        self.dest_path = other.dest_path.or(self.dest_path.take());
+        self.package_path = other.package_path.or(self.package_path.take());
+        self.chroot_method = other.chroot_method.or(self.chroot_method);
+        self.packager = other.packager.or(self.packager);
+        self.jobs = other.jobs.or(self.jobs);
+        self.cpus = other.cpus.or(self.cpus);
+        self.strict = other.strict.or(self.strict);
+        self.offline = other.offline.or(self.offline);
+        self.mirrors = other.mirrors.or(self.mirrors.take());
+        self.cache_server = other.cache_server.or(self.cache_server.take());
+        self.fetch_retries = other.fetch_retries.or(self.fetch_retries);
+        self.fetch_timeout = other.fetch_timeout.or(self.fetch_timeout);
+    }
+
+    pub fn apply_env(&mut self) -> anyhow::Result<()> {
+        let paths = [
+            ("HOB_CACHE_PATH", &mut self.cache_path),
+            ("HOB_ROOT_PATH", &mut self.root_path),
+            ("HOB_SOURCE_PATH", &mut self.source_path),
+            ("HOB_DEST_PATH", &mut self.dest_path),
+            ("HOB_PACKAGE_PATH", &mut self.package_path),
+        ];
+
+        for (name, into) in paths {
+            if let Some(value) = std::env::var_os(name) {
+                *into = Some(PathBuf::from(value));
+            }
+        }
+
+        if let Ok(value) = std::env::var("HOB_CHROOT_METHOD") {
+            self.chroot_method = Some(
+                ChrootMethod::parse(&value)
+                    .ok_or_else(|| anyhow!("HOB_CHROOT_METHOD has unknown value {}", value))?,
+            );
+        }
+
+        if let Ok(value) = std::env::var("HOB_PACKAGER") {
+            self.packager = Some(
+                PackagerType::parse(&value)
+                    .ok_or_else(|| anyhow!("HOB_PACKAGER has unknown value {}", value))?,
+            );
+        }
+
+        self.jobs = env_usize("HOB_JOBS")?.or(self.jobs);
+        self.cpus = env_usize("HOB_CPUS")?.or(self.cpus);
+
+        self.fetch_retries = env_usize("HOB_FETCH_RETRIES")?.or(self.fetch_retries);
+        self.fetch_timeout = env_usize("HOB_FETCH_TIMEOUT")?.or(self.fetch_timeout);
+
+        if let Ok(value) = std::env::var("HOB_MIRRORS") {
+            self.mirrors = Some(value.split_whitespace().map(str::to_string).collect());
+        }
+
+        if let Ok(value) = std::env::var("HOB_CACHE_SERVER") {
+            self.cache_server = Some(value);
+        }
+
+        self.strict = env_bool("HOB_STRICT")?.or(self.strict);
+        self.offline = env_bool("HOB_OFFLINE")?.or(self.offline);
+
+        Ok(())
+    }
+
+    pub fn into_settings(self) -> EngineSettings {
+        EngineSettings {
+            cache_path: self
+                .cache_path
+                .unwrap_or_else(|| PathBuf::from("/tmp/hob/cache")),
+            source_path: self
+                .source_path
+                .unwrap_or_else(|| PathBuf::from(".hob/src")),
+            dest_path: self.dest_path.unwrap_or_else(|| PathBuf::from(".hob/dest")),
+            root_path: self
+                .root_path
+                .unwrap_or_else(|| PathBuf::from("/tmp/hob/root")),
+            package_path: self
+                .package_path
+                .unwrap_or_else(|| PathBuf::from(".hob/pkg")),
+            chroot_method: self.chroot_method.unwrap_or(ChrootMethod::SystemChroot),
+            packager: self.packager.unwrap_or(PackagerType::Apk),
+            jobs: self.jobs.unwrap_or(1).max(1),
+            cpus: self.cpus.unwrap_or_else(num_cpus::get).max(1),
+            strict: self.strict.unwrap_or(false),
+            offline: self.offline.unwrap_or(false),
+            mirrors: self.mirrors.unwrap_or_default(),
+            cache_server: self.cache_server,
+            fetch_retries: self.fetch_retries.unwrap_or(3),
+            fetch_timeout: Duration::from_secs(self.fetch_timeout.unwrap_or(30).max(1) as u64),
+        }
+    }
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("hob/config.kdl"))
+}
+
+fn env_usize(name: &str) -> anyhow::Result<Option<usize>> {
+    match std::env::var(name) {
+        Ok(value) => usize::from_str(&value)
+            .map(Some)
+            .with_context(|| format!("{} should be a non-negative integer", name)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn env_bool(name: &str) -> anyhow::Result<Option<bool>> {
+    match std::env::var(name) {
+        Ok(value) => match value.as_str() {
+            "1" | "true" | "yes" => Ok(Some(true)),
+            "0" | "false" | "no" => Ok(Some(false)),
+            _ => bail!("{} should be true or false, found {}", name, value),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+fn parse_path(node: &KdlNode, errors: &mut Vec<HobParseError>) -> Option<PathBuf> {
+    let mut path: Option<String> = None;
+    parse_string_into!(node, path, errors, "path");
+    path.map(PathBuf::from)
+}
+
+impl ParseDocument for EngineConfig {
+    fn parse_document_with_errors(input: &KdlDocument) -> (Option<Self>, Vec<HobParseError>)
+    where
+        Self: Sized,
+    {
+        let mut config = EngineConfig::default();
+        let mut errors = vec![];
+
+        for node in input.nodes() {
+            match node.name().value() {
+                "cache-path" => config.cache_path = parse_path(node, &mut errors),
+                "root-path" => config.root_path = parse_path(node, &mut errors),
+                "source-path" => config.source_path = parse_path(node, &mut errors),
+                "dest-path" => config.dest_path = parse_path(node, &mut errors),
+                "package-path" => config.package_path = parse_path(node, &mut errors),
+
+                "chroot-method" => {
+                    let mut method: Option<String> = None;
+                    parse_string_into!(node, method, errors, "chroot-method");
+
+                    if let Some(method) = method {
+                        match ChrootMethod::parse(method) {
+                            Some(method) => config.chroot_method = Some(method),
+                            None => errors.push(HobParseError {
+                                span: *node.entries().first().unwrap().span(),
+                                label: None,
+                                help: Some("expected one of: none, chroot".to_string()),
+                                kind: "unknown chroot method",
+                            }),
+                        }
+                    }
+                }
+
+                "packager" => {
+                    let mut packager: Option<String> = None;
+                    parse_string_into!(node, packager, errors, "packager");
+
+                    if let Some(packager) = packager {
+                        match PackagerType::parse(packager) {
+                            Some(packager) => config.packager = Some(packager),
+                            None => errors.push(HobParseError {
+                                span: *node.entries().first().unwrap().span(),
+                                label: None,
+                                help: Some("expected one of: apk".to_string()),
+                                kind: "unknown packager",
+                            }),
+                        }
+                    }
+                }
+
+                "jobs" => {
+                    parse_usize_into!(node, config.jobs, errors, "jobs");
+                }
+
+                "cpus" => {
+                    parse_usize_into!(node, config.cpus, errors, "cpus");
+                }
+
+                "strict" => {
+                    parse_bool_into!(node, config.strict, errors, "strict");
+                }
+
+                "offline" => {
+                    parse_bool_into!(node, config.offline, errors, "offline");
+                }
+
+                "mirrors" => {
+                    let mut mirrors = vec![];
+                    parse_string_list_into!(node, mirrors, errors, "mirrors");
+                    config.mirrors = Some(mirrors);
+                }
+
+                "cache-server" => {
+                    parse_string_into!(node, config.cache_server, errors, "url");
+                }
+
+                "fetch-retries" => {
+                    parse_usize_into!(node, config.fetch_retries, errors, "fetch-retries");
+                }
+
+                "fetch-timeout" => {
+                    parse_usize_into!(node, config.fetch_timeout, errors, "fetch-timeout");
+                }
+
+                _ => errors.push(HobParseError {
+                    span: *node.name().span(),
+                    label: None,
+                    help: None,
+                    kind: "unknown configuration option",
+                }),
+            }
+        }
+
+        (Some(config), errors)
+    }
+}
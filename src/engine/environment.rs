@@ -13,9 +13,9 @@ pub struct Environment {
 impl Environment {
     pub fn new(settings: Arc<EngineSettings>) -> Self {
         Environment {
+            cpus: settings.cpus,
             settings,
             bootstrap: true,
-            cpus: num_cpus::get(),
         }
     }
 
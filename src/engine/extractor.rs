@@ -1,46 +1,109 @@
+use crate::definition::{Archive, ArchiveFormat, ArtifactSource, Compression};
+use crate::engine::archives::{
+    unpack_cpio, unpack_deb, unpack_rpm, AR_MAGIC, CPIO_MAGIC, DEB_FIRST_MEMBER, RPM_LEAD_MAGIC,
+};
 use crate::engine::fetcher::FetchedArtifact;
+use crate::engine::git::GitMirror;
 use crate::engine::EngineSettings;
-use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder};
-use std::ffi::OsStr;
-use std::path::Path;
+use crate::Recipe;
+use async_compression::tokio::bufread::{
+    BzDecoder, GzipDecoder, LzmaDecoder, XzDecoder, ZstdDecoder,
+};
+use async_zip::tokio::read::fs::ZipFileReader;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::collections::HashSet;
+use std::fs::Permissions;
+use std::io::{Cursor, ErrorKind};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, Chain,
+    ReadBuf,
+};
 
 #[derive(Debug)]
 pub struct Extractor {
     settings: Arc<EngineSettings>,
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-enum Archive {
-    Zip,
-    Tar,
-}
+/// Magic bytes at the start of compressed files.
+const COMPRESSION_MAGIC: &[(&[u8], Compression)] = &[
+    (&[0x1f, 0x8b], Compression::Gzip),
+    (&[0xfd, b'7', b'z', b'X', b'Z', 0x00], Compression::Xz),
+    (b"BZh", Compression::Bz),
+    (&[0x28, 0xb5, 0x2f, 0xfd], Compression::Zstd),
+    (b"LZIP", Compression::Lzip),
+    // lzma has no magic, but practically always uses the default properties and a dictionary
+    // size that is a multiple of 64KiB
+    (&[0x5d, 0x00, 0x00], Compression::Lzma),
+];
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
-enum Compression {
-    None,
-    Gzip,
-    Xz,
-    Bz,
-}
+const ZIP_MAGIC: &[&[u8]] = &[b"PK\x03\x04", b"PK\x05\x06"];
 
-const GUESSES: &[(&'static str, Archive, Compression)] = &[
-    (".tar.gz", Archive::Tar, Compression::Gzip),
-    (".tar.xz", Archive::Tar, Compression::Xz),
-    (".tar.bz", Archive::Tar, Compression::Bz),
-    (".tar", Archive::Tar, Compression::None),
-    (".zip", Archive::Zip, Compression::None),
-];
+pub(crate) const S_IFMT: u32 = 0o170000;
+pub(crate) const S_IFLNK: u32 = 0o120000;
 
-enum Decompressor<R: AsyncBufRead> {
+/// Offset of the `ustar` magic in a tar header.
+const TAR_MAGIC_OFFSET: usize = 257;
+
+pub(crate) enum Decompressor<R: AsyncBufRead> {
     PassThrough(R),
     Xz(XzDecoder<R>),
     Gzip(GzipDecoder<R>),
     Bz(BzDecoder<R>),
+    Zstd(ZstdDecoder<R>),
+    Lzma(LzmaDecoder<R>),
+    Lzip(LzmaDecoder<Chain<Cursor<Vec<u8>>, R>>),
+}
+
+impl<R: AsyncBufRead + Unpin> Decompressor<R> {
+    async fn new(mut read: R, compression: Compression) -> std::io::Result<Self> {
+        Ok(match compression {
+            Compression::None => Decompressor::PassThrough(read),
+            Compression::Gzip => Decompressor::Gzip(GzipDecoder::new(read)),
+            Compression::Xz => Decompressor::Xz(XzDecoder::new(read)),
+            Compression::Bz => Decompressor::Bz(BzDecoder::new(read)),
+            Compression::Zstd => Decompressor::Zstd(ZstdDecoder::new(read)),
+            Compression::Lzma => Decompressor::Lzma(LzmaDecoder::new(read)),
+            Compression::Lzip => {
+                let mut header = [0; 6];
+                read.read_exact(&mut header).await?;
+                let header = lzma_header_for_lzip(&header)?;
+
+                Decompressor::Lzip(LzmaDecoder::new(Cursor::new(header).chain(read)))
+            }
+        })
+    }
+}
+
+/// Translates an lzip header into the header of a plain lzma stream.
+///
+/// Lzip members are lzma streams with fixed properties and an end marker, so only the header
+/// differs, the trailer after the end marker is never read. Only the first member of an lzip
+/// file is decompressed, which is all `lzip` itself writes for a single input.
+fn lzma_header_for_lzip(header: &[u8; 6]) -> std::io::Result<Vec<u8>> {
+    if &header[..4] != b"LZIP" || header[4] != 1 {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "not an lzip version 1 file",
+        ));
+    }
+
+    // the base size is a power of two, from which up to 7 sixteenths are subtracted
+    let base = 1u32 << (header[5] & 0x1f);
+    let dict_size = base - (base / 16) * (header[5] >> 5) as u32;
+
+    let mut lzma = vec![0x5d];
+    lzma.extend_from_slice(&dict_size.to_le_bytes());
+    // unknown uncompressed size, the stream ends with an end marker instead
+    lzma.extend_from_slice(&[0xff; 8]);
+
+    Ok(lzma)
 }
 
 impl<R: AsyncBufRead + Unpin> AsyncRead for Decompressor<R> {
@@ -54,6 +117,9 @@ impl<R: AsyncBufRead + Unpin> AsyncRead for Decompressor<R> {
             Decompressor::Xz(r) => AsyncRead::poll_read(Pin::new(r), cx, buf),
             Decompressor::Gzip(r) => AsyncRead::poll_read(Pin::new(r), cx, buf),
             Decompressor::Bz(r) => AsyncRead::poll_read(Pin::new(r), cx, buf),
+            Decompressor::Zstd(r) => AsyncRead::poll_read(Pin::new(r), cx, buf),
+            Decompressor::Lzma(r) => AsyncRead::poll_read(Pin::new(r), cx, buf),
+            Decompressor::Lzip(r) => AsyncRead::poll_read(Pin::new(r), cx, buf),
         }
     }
 }
@@ -63,74 +129,604 @@ impl Extractor {
         Extractor { settings }
     }
 
-    pub async fn extract<'a, P: AsRef<Path>>(
+    /// Puts an artifact into the sources of `recipe`, unpacking archives unless the artifact
+    /// says otherwise.
+    pub async fn extract<'a>(
         &self,
         artifact: &FetchedArtifact<'a>,
-        path: P,
+        recipe: &Recipe,
     ) -> anyhow::Result<()> {
-        let path = self.settings.source_path().join(path);
-        tokio::fs::create_dir_all(&path).await?;
-
-        let found = GUESSES
-            .iter()
-            .filter_map(|(ext, arch, comp)| {
-                if artifact
-                    .path
-                    .file_name()
-                    .and_then(OsStr::to_str)
-                    .map_or(false, |x| x.ends_with(*ext))
-                {
-                    Some((*arch, *comp))
-                } else {
-                    None
-                }
-            })
-            .next();
+        let path = self.settings.source_path_for_recipe(recipe);
+        let extraction = &artifact.artifact.extraction;
+        let dest = match &extraction.destination {
+            Some(destination) => path.join(destination),
+            None => path.clone(),
+        };
+        tokio::fs::create_dir_all(&dest).await?;
+
+        match &artifact.artifact.source {
+            ArtifactSource::Git(git) => {
+                let commit = artifact
+                    .commit
+                    .as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("git artifact {} wasn't resolved", git.url))?;
+
+                // a destination is where the checkout goes, not the directory it goes in
+                let checkout = match extraction.destination {
+                    Some(_) => dest,
+                    None => path.join(git.file_name()),
+                };
+
+                return GitMirror::new(&artifact.path)
+                    .checkout(git, commit, &checkout)
+                    .await;
+            }
+
+            ArtifactSource::File(local) => {
+                let dir = match extraction.destination {
+                    Some(_) => dest,
+                    None => self.settings.extracted_source_path_for_recipe(recipe),
+                };
+
+                return copy_into(&artifact.path, &dir, local.file_name()).await;
+            }
 
-        let (arch, compr) = match found {
-            None => {
-                anyhow::bail!("couldn't guess archive type")
+            ArtifactSource::Patch(patch) => {
+                let dest = self.settings.patch_path_for_recipe(recipe);
+                return copy_into(&artifact.path, &dest, patch.file.file_name()).await;
             }
 
-            Some(x) => x,
+            ArtifactSource::Fetch(fetch) if !extraction.extract => {
+                return copy_into(&artifact.path, &dest, fetch.file_name()).await;
+            }
+
+            ArtifactSource::Fetch(_) => {}
+        }
+
+        if extraction.strip_components == 0 {
+            return unpack(artifact, &dest).await;
+        }
+
+        // unpack next to the sources first, so stripped entries never touch them
+        let staging = self.settings.staging_path_for_recipe(recipe);
+        if tokio::fs::metadata(&staging).await.is_ok() {
+            tokio::fs::remove_dir_all(&staging).await?;
+        }
+        tokio::fs::create_dir_all(&staging).await?;
+
+        let res = match unpack(artifact, &staging).await {
+            Ok(()) => strip_into(&staging, &dest, extraction.strip_components)
+                .await
+                .map_err(Into::into),
+            Err(e) => Err(e),
         };
+        tokio::fs::remove_dir_all(&staging).await?;
 
-        let read = OpenOptions::new()
-            .read(true)
-            .write(false)
-            .create(false)
-            .open(&artifact.path)
-            .await?;
+        res
+    }
+}
 
-        let read = tokio::io::BufReader::new(read);
-        let read = match compr {
-            Compression::None => Decompressor::PassThrough(read),
-            Compression::Gzip => Decompressor::Gzip(GzipDecoder::new(read)),
-            Compression::Xz => Decompressor::Xz(XzDecoder::new(read)),
-            Compression::Bz => Decompressor::Bz(BzDecoder::new(read)),
+async fn unpack(artifact: &FetchedArtifact<'_>, dest: &Path) -> anyhow::Result<()> {
+    let format = detect_format(artifact).await?;
+    if format.archive == Archive::Zip {
+        if format.compression != Compression::None {
+            anyhow::bail!("zip archives can't be compressed as a whole");
+        }
+
+        return extract_zip(&artifact.path, dest).await;
+    }
+
+    let read = BufReader::new(open(&artifact.path).await?);
+    let read = Decompressor::new(read, format.compression).await?;
+
+    match format.archive {
+        Archive::Tar => tokio_tar::Archive::new(read).unpack(dest).await?,
+        Archive::Cpio => unpack_cpio(read, dest).await?,
+        Archive::Deb => unpack_deb(read, dest).await?,
+        Archive::Rpm => unpack_rpm(read, dest).await?,
+        Archive::Zip => unreachable!("zip archives are read from the file directly"),
+    }
+
+    Ok(())
+}
+
+/// Wraps `read` in a decompressor for the compression its first bytes indicate.
+pub(crate) async fn decompress_sniffed<R: AsyncBufRead + Unpin>(
+    mut read: R,
+) -> std::io::Result<Decompressor<R>> {
+    let compression = sniff_compression(read.fill_buf().await?);
+    Decompressor::new(read, compression).await
+}
+
+fn sniff_compression(head: &[u8]) -> Compression {
+    COMPRESSION_MAGIC
+        .iter()
+        .find(|(magic, _)| head.starts_with(magic))
+        .map_or(Compression::None, |(_, compression)| *compression)
+}
+
+/// Moves everything `depth` directories deep in `from` into `to`, dropping whatever is less
+/// deep, like `tar --strip-components`.
+async fn strip_into(from: &Path, to: &Path, depth: usize) -> std::io::Result<()> {
+    let mut level = vec![from.to_path_buf()];
+    for _ in 0..depth {
+        let mut next = vec![];
+        for dir in level {
+            let mut entries = tokio::fs::read_dir(&dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                if entry.file_type().await?.is_dir() {
+                    next.push(entry.path());
+                }
+            }
+        }
+
+        level = next;
+    }
+
+    for dir in level {
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            move_into(&entry.path(), &to.join(entry.file_name())).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves `from` to `to`, merging directories that exist in both and replacing anything else.
+fn move_into<'a>(from: &'a Path, to: &'a Path) -> BoxFuture<'a, std::io::Result<()>> {
+    async move {
+        let existing = match tokio::fs::symlink_metadata(to).await {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == ErrorKind::NotFound => return tokio::fs::rename(from, to).await,
+            Err(e) => return Err(e),
         };
 
-        match arch {
-            Archive::Zip => {
-                let mut archive = async_zip::read::stream::ZipFileReader::new(read);
-                while let Some(mut reader) = archive.entry_reader().await? {
-                    let entry = reader.entry();
-                    let child_path = path.join(entry.filename()).canonicalize()?;
-
-                    if let Some(p) = child_path.parent() {
-                        tokio::fs::create_dir_all(p).await?;
-                    }
-                    let mut f = File::create(child_path).await?;
-                    tokio::io::copy(&mut reader, &mut f).await?;
-                    f.sync_all().await?;
+        let is_dir = tokio::fs::symlink_metadata(from).await?.is_dir();
+        if is_dir && existing.is_dir() {
+            let mut entries = tokio::fs::read_dir(from).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                move_into(&entry.path(), &to.join(entry.file_name())).await?;
+            }
+
+            return tokio::fs::remove_dir(from).await;
+        }
+
+        if existing.is_dir() {
+            tokio::fs::remove_dir_all(to).await?;
+        } else {
+            tokio::fs::remove_file(to).await?;
+        }
+
+        tokio::fs::rename(from, to).await
+    }
+    .boxed()
+}
+
+/// Extracts a zip archive into `dest`, restoring unix permissions and symlinks.
+///
+/// Entries and symlinks pointing outside of `dest` are refused, so a malicious archive can't
+/// write anywhere else.
+async fn extract_zip(file: &Path, dest: &Path) -> anyhow::Result<()> {
+    let archive = ZipFileReader::new(file).await?;
+    let mut root = ArchiveRoot::new(dest).await?;
+
+    for (index, entry) in archive.file().entries().iter().enumerate() {
+        let name = entry.filename().as_str()?;
+        let relative = contained_path(Path::new(name)).ok_or_else(|| {
+            anyhow::anyhow!(
+                "zip entry {} would be extracted outside of the sources",
+                name
+            )
+        })?;
+        let mode = entry.unix_permissions().map(u32::from);
+
+        if entry.dir()? {
+            let target = root.create_dir(&relative).await?;
+            if let Some(mode) = mode {
+                // keep directories writable, or the entries inside couldn't be extracted
+                set_mode(&target, mode | 0o700).await?;
+            }
+
+            continue;
+        }
+
+        let mut reader = archive.reader_with_entry(index).await?;
+
+        if mode.is_some_and(|mode| mode & S_IFMT == S_IFLNK) {
+            let mut link = vec![];
+            reader.read_to_end_checked(&mut link).await?;
+            let link = PathBuf::from(String::from_utf8(link)?);
+
+            root.symlink(&relative, &link).await?;
+            continue;
+        }
+
+        let target = root.prepare(&relative).await?;
+        let mut out = File::create(&target).await?;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = futures::AsyncReadExt::read(&mut reader, &mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+
+            out.write_all(&buffer[..read]).await?;
+        }
+
+        if reader.compute_hash() != entry.crc32() {
+            anyhow::bail!("zip entry {} is corrupt", name);
+        }
+
+        out.sync_all().await?;
+        if let Some(mode) = mode {
+            set_mode(&target, mode).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Normalizes a relative path, returning `None` if it is absolute or leaves its root through
+/// `..`.
+pub(crate) fn contained_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
                 }
             }
-            Archive::Tar => {
-                let mut archive = tokio_tar::Archive::new(read);
-                archive.unpack(path).await?;
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    Some(normalized)
+}
+
+/// The directory the entries of an archive are extracted into.
+///
+/// Checking the names of entries alone isn't enough, `d -> .` followed by `l -> d/..` are two
+/// harmless looking links, but `l/x` ends up next to `dest`. So entries may not go through a
+/// symlink of the same archive, and the real path of the directory an entry is written to has to
+/// be inside of `dest`, like tar-rs checks in `unpack_in`.
+pub(crate) struct ArchiveRoot {
+    dest: PathBuf,
+    real_dest: PathBuf,
+    symlinks: HashSet<PathBuf>,
+}
+
+impl ArchiveRoot {
+    pub(crate) async fn new(dest: &Path) -> std::io::Result<Self> {
+        Ok(ArchiveRoot {
+            dest: dest.to_path_buf(),
+            real_dest: tokio::fs::canonicalize(dest).await?,
+            symlinks: HashSet::new(),
+        })
+    }
+
+    /// Returns where the entry at `relative`, as returned by `contained_path`, is written,
+    /// creating the directories it is in.
+    ///
+    /// A symlink already there is removed, so writing the entry doesn't follow it.
+    pub(crate) async fn prepare(&self, relative: &Path) -> anyhow::Result<PathBuf> {
+        self.check_symlinks(relative)?;
+
+        let parent = self.dest.join(relative.parent().unwrap_or(Path::new("")));
+        tokio::fs::create_dir_all(&parent).await?;
+        self.check_real_path(&parent, relative).await?;
+
+        let target = self.dest.join(relative);
+        if let Ok(metadata) = tokio::fs::symlink_metadata(&target).await {
+            if metadata.is_symlink() {
+                tokio::fs::remove_file(&target).await?;
             }
         }
 
+        Ok(target)
+    }
+
+    /// Creates the directory entry at `relative`.
+    pub(crate) async fn create_dir(&self, relative: &Path) -> anyhow::Result<PathBuf> {
+        self.check_symlinks(relative)?;
+
+        let target = self.dest.join(relative);
+        tokio::fs::create_dir_all(&target).await?;
+        self.check_real_path(&target, relative).await?;
+
+        Ok(target)
+    }
+
+    /// Creates the symlink entry at `relative`, which later entries may not go through.
+    pub(crate) async fn symlink(&mut self, relative: &Path, link: &Path) -> anyhow::Result<()> {
+        // links are resolved relative to their own directory
+        let resolved = relative.parent().unwrap_or(Path::new("")).join(link);
+        if contained_path(&resolved).is_none() {
+            anyhow::bail!(
+                "archive entry {} links to {} outside of the sources",
+                relative.display(),
+                link.display()
+            );
+        }
+
+        let target = self.prepare(relative).await?;
+        remove_if_exists(&target).await?;
+        tokio::fs::symlink(link, &target).await?;
+        self.symlinks.insert(relative.to_path_buf());
+
+        Ok(())
+    }
+
+    fn check_symlinks(&self, relative: &Path) -> anyhow::Result<()> {
+        if relative
+            .ancestors()
+            .skip(1)
+            .any(|dir| self.symlinks.contains(dir))
+        {
+            anyhow::bail!(
+                "archive entry {} goes through a symlink of the archive",
+                relative.display()
+            );
+        }
+
         Ok(())
     }
+
+    async fn check_real_path(&self, dir: &Path, relative: &Path) -> anyhow::Result<()> {
+        if !tokio::fs::canonicalize(dir)
+            .await?
+            .starts_with(&self.real_dest)
+        {
+            anyhow::bail!(
+                "archive entry {} would be extracted outside of the sources",
+                relative.display()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) async fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    tokio::fs::set_permissions(path, Permissions::from_mode(mode & 0o7777)).await
+}
+
+pub(crate) async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Picks the format of a fetched artifact: the one given in the recipe, otherwise what the
+/// contents look like, falling back to the extension for what the contents don't tell.
+async fn detect_format(artifact: &FetchedArtifact<'_>) -> anyhow::Result<ArchiveFormat> {
+    let fetch = match &artifact.artifact.source {
+        ArtifactSource::Fetch(fetch) => fetch,
+        _ => unreachable!("only fetched artifacts are extracted as archives"),
+    };
+
+    if let Some(format) = fetch.format {
+        return Ok(format);
+    }
+
+    let (compression, archive) = sniff_format(&artifact.path).await?;
+    let guess = ArchiveFormat::from_file_name(fetch.file_name());
+
+    Ok(match (archive, guess) {
+        (Some(archive), _) => ArchiveFormat::new(archive, compression),
+        (None, Some(guess)) if compression == Compression::None => guess,
+        // e.g. old tar archives without the ustar magic
+        (None, Some(guess)) => ArchiveFormat::new(guess.archive, compression),
+        (None, None) => anyhow::bail!(
+            "couldn't guess archive type of {}, set it with `format`",
+            fetch.file_name()
+        ),
+    })
+}
+
+/// Detects the compression of a file by its magic bytes, and the archive inside by looking at
+/// the start of the decompressed data.
+async fn sniff_format(path: &Path) -> anyhow::Result<(Compression, Option<Archive>)> {
+    let head = read_head(open(path).await?).await?;
+
+    if ZIP_MAGIC.iter().any(|magic| head.starts_with(magic)) {
+        return Ok((Compression::None, Some(Archive::Zip)));
+    }
+
+    if head.starts_with(AR_MAGIC) && head[AR_MAGIC.len()..].starts_with(DEB_FIRST_MEMBER) {
+        return Ok((Compression::None, Some(Archive::Deb)));
+    }
+
+    if head.starts_with(RPM_LEAD_MAGIC) {
+        return Ok((Compression::None, Some(Archive::Rpm)));
+    }
+
+    let compression = sniff_compression(&head);
+
+    let inner = if compression == Compression::None {
+        head
+    } else {
+        // corrupt data is reported properly once it's extracted
+        match Decompressor::new(BufReader::new(open(path).await?), compression).await {
+            Ok(read) => read_head(read).await.unwrap_or_default(),
+            Err(_) => vec![],
+        }
+    };
+
+    if CPIO_MAGIC.iter().any(|magic| inner.starts_with(magic)) {
+        return Ok((compression, Some(Archive::Cpio)));
+    }
+
+    let is_tar = inner
+        .get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5)
+        .is_some_and(|magic| magic == b"ustar");
+
+    Ok((compression, is_tar.then_some(Archive::Tar)))
+}
+
+/// Reads the first 512 bytes, or less if the data is shorter.
+async fn read_head<R: AsyncRead + Unpin>(read: R) -> std::io::Result<Vec<u8>> {
+    let mut head = vec![];
+    read.take(512).read_to_end(&mut head).await?;
+
+    Ok(head)
+}
+
+async fn open(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(false)
+        .create(false)
+        .open(path)
+        .await
+}
+
+async fn copy_into(source: &Path, dir: &Path, file_name: &str) -> anyhow::Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::copy(source, dir.join(file_name)).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use async_zip::base::write::ZipFileWriter;
+    use async_zip::{Compression as ZipCompression, ZipEntryBuilder};
+
+    pub(crate) async fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hob-{}-{}", name, std::process::id()));
+        if tokio::fs::metadata(&dir).await.is_ok() {
+            tokio::fs::remove_dir_all(&dir).await.unwrap();
+        }
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        dir
+    }
+
+    async fn write_zip(path: &Path, entries: &[(&str, u16, &[u8])]) {
+        let mut writer = ZipFileWriter::with_tokio(vec![]);
+        for (name, mode, data) in entries {
+            let entry = ZipEntryBuilder::new((*name).into(), ZipCompression::Stored)
+                .unix_permissions(*mode);
+            writer.write_entry_whole(entry, data).await.unwrap();
+        }
+
+        let zip = writer.close().await.unwrap().into_inner();
+        tokio::fs::write(path, zip).await.unwrap();
+    }
+
+    /// `hob lzip\n` as written by lzip, an lzma stream with an end marker and a trailer.
+    const LZIP_STREAM: &[u8] = &[
+        0x4c, 0x5a, 0x49, 0x50, 0x01, 0x10, 0x00, 0x34, 0x1b, 0xc8, 0x65, 0x48, 0x9a, 0xde, 0x41,
+        0x67, 0x3f, 0x01, 0xb0, 0x98, 0xff, 0xff, 0xb7, 0x98, 0x00, 0x00, 0x0c, 0x67, 0x50, 0xc3,
+        0x09, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2e, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    #[tokio::test]
+    async fn lzip_round_trip() {
+        assert_eq!(sniff_compression(LZIP_STREAM), Compression::Lzip);
+
+        let mut read = Decompressor::new(BufReader::new(LZIP_STREAM), Compression::Lzip)
+            .await
+            .unwrap();
+        let mut data = vec![];
+        read.read_to_end(&mut data).await.unwrap();
+
+        assert_eq!(data, b"hob lzip\n");
+    }
+
+    #[test]
+    fn lzip_dictionary_size() {
+        let header = lzma_header_for_lzip(b"LZIP\x01\x17").unwrap();
+        assert_eq!(header[..5], [0x5d, 0x00, 0x00, 0x80, 0x00]);
+
+        // 1MiB minus 7 sixteenths of it
+        let header = lzma_header_for_lzip(b"LZIP\x01\xf4").unwrap();
+        assert_eq!(header[1..5], (589824u32).to_le_bytes());
+
+        assert!(lzma_header_for_lzip(b"LZIP\x00\x17").is_err());
+    }
+
+    #[test]
+    fn lzma_sniff_needs_default_properties() {
+        assert_eq!(
+            sniff_compression(&[0x5d, 0x00, 0x00, 0x01, 0x00]),
+            Compression::Lzma
+        );
+
+        // a text file starting with `]`, and other properties than lzma uses by default
+        assert_eq!(sniff_compression(b"]]\n"), Compression::None);
+        assert_eq!(
+            sniff_compression(&[0x5e, 0x00, 0x00, 0x01, 0x00]),
+            Compression::None
+        );
+        assert_eq!(
+            sniff_compression(&[0x5d, 0x00, 0x01, 0x00, 0x00]),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn contained_path_normalizes() {
+        assert_eq!(
+            contained_path(Path::new("./a/b/../c")),
+            Some(PathBuf::from("a/c"))
+        );
+        assert_eq!(contained_path(Path::new("a/..")), Some(PathBuf::new()));
+        assert_eq!(contained_path(Path::new("d/..")), Some(PathBuf::new()));
+    }
+
+    #[test]
+    fn contained_path_refuses_escapes() {
+        assert_eq!(contained_path(Path::new("/etc/passwd")), None);
+        assert_eq!(contained_path(Path::new("..")), None);
+        assert_eq!(contained_path(Path::new("a/../../b")), None);
+    }
+
+    #[tokio::test]
+    async fn zip_symlink_chain_stays_inside() {
+        let dir = scratch_dir("zip-symlink-chain").await;
+        let dest = dir.join("sources");
+        tokio::fs::create_dir_all(&dest).await.unwrap();
+
+        // `l` passes the check of its name, but points at the parent of `dest`
+        let zip = dir.join("evil.zip");
+        write_zip(
+            &zip,
+            &[
+                ("d", 0o120777, b"."),
+                ("l", 0o120777, b"d/.."),
+                ("l/x", 0o100644, b"escaped"),
+            ],
+        )
+        .await;
+
+        assert!(extract_zip(&zip, &dest).await.is_err());
+        assert!(tokio::fs::metadata(dir.join("x")).await.is_err());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn real_path_outside_is_refused() {
+        let dir = scratch_dir("real-path").await;
+        let dest = dir.join("sources");
+        tokio::fs::create_dir_all(&dest).await.unwrap();
+
+        // a link left in the sources by something else than the archive
+        tokio::fs::symlink("..", dest.join("up")).await.unwrap();
+
+        let root = ArchiveRoot::new(&dest).await.unwrap();
+        assert!(root.prepare(Path::new("up/x")).await.is_err());
+        assert!(root.create_dir(Path::new("up/y")).await.is_err());
+        assert!(root.prepare(Path::new("inside/x")).await.is_ok());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
 }
@@ -1,14 +1,28 @@
-use crate::definition::{Artifact, ArtifactSource, Verification};
-use crate::engine::EngineSettings;
+use crate::definition::{
+    Artifact, ArtifactSource, ChecksumAlgorithm, Extraction, FetchArtifact, GitReference,
+    Signature, Verification,
+};
+use crate::engine::git::GitMirror;
+use crate::engine::signature::verify_signature;
+use crate::engine::{EngineError, EngineSettings};
+use crate::Recipe;
+use anyhow::{anyhow, bail};
+use blake2::{Blake2b512, Digest};
 use hex::ToHex;
-use reqwest::Client;
-use ring::digest::{Context, SHA256};
+use miette::Diagnostic;
+use reqwest::header::RANGE;
+use reqwest::{Client, StatusCode};
+use ring::digest::{Context, SHA256, SHA512};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 use std::io::ErrorKind;
 
+use std::cmp::min;
+use std::collections::HashMap;
+use std::future::Future;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
@@ -16,12 +30,31 @@ use tokio::io::{AsyncReadExt, AsyncWriteExt};
 pub struct Fetcher {
     settings: Arc<EngineSettings>,
     http_client: Client,
+    /// Git artifacts of the same repository share a mirror, which is cloned and updated by one
+    /// of them at a time.
+    mirror_locks: Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>,
 }
 
 #[derive(Debug)]
 pub struct FetchedArtifact<'a> {
     pub artifact: &'a Artifact,
     pub path: PathBuf,
+    /// Commit a git artifact was resolved to.
+    pub commit: Option<String>,
+}
+
+impl FetchedArtifact<'_> {
+    /// Identifies what the artifact puts into the sources, its [`Artifact::hash_id`] and for git
+    /// artifacts the commit that was checked out, so a moved tag or branch is a new source.
+    pub fn source_id(&self) -> [u8; 32] {
+        let mut digest = Context::new(&SHA256);
+        digest.update(&self.artifact.hash_id());
+        if let Some(commit) = &self.commit {
+            digest.update(commit.as_bytes());
+        }
+
+        digest.finish().as_ref().try_into().unwrap()
+    }
 }
 
 #[derive(Debug)]
@@ -40,27 +73,19 @@ impl Display for HobFetchError {
             HobFetchErrorKind::VerificationFailed { hashes } => {
                 write!(f, "verification failed (")?;
                 let mut first = true;
-                for FailedHash {
-                    algo,
-                    found,
-                    expected,
-                } in hashes
-                {
+                for hash in hashes {
                     if !first {
                         write!(f, ", ")?;
                     }
 
                     first = false;
-                    write!(
-                        f,
-                        "{} expected {} but found {}",
-                        algo,
-                        hex::encode(expected),
-                        hex::encode(found)
-                    )?;
+                    write!(f, "{}", hash)?;
                 }
                 write!(f, ")")?;
             }
+            HobFetchErrorKind::SignatureFailed { reason } => {
+                write!(f, "signature verification failed ({})", reason)?;
+            }
             HobFetchErrorKind::_Other => {
                 write!(f, "unknown error occurred")?;
             }
@@ -83,6 +108,15 @@ impl Display for HobFetchError {
     }
 }
 
+#[derive(Debug, Diagnostic, thiserror::Error)]
+#[error("{} artifacts are missing from the cache, which is required in offline mode:\n{}", .missing.len(), .missing.join("\n"))]
+#[diagnostic(help(
+    "run `hob fetch` for these recipes on a machine with network access and copy its cache over"
+))]
+pub struct HobOfflineError {
+    pub missing: Vec<String>,
+}
+
 #[derive(Debug)]
 pub struct FailedHash {
     algo: &'static str,
@@ -90,6 +124,18 @@ pub struct FailedHash {
     expected: Box<[u8]>,
 }
 
+impl Display for FailedHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} expected {} but found {}",
+            self.algo,
+            hex::encode(&self.expected),
+            hex::encode(&self.found)
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum HobFetchAffected {
     Fetched,
@@ -99,39 +145,91 @@ pub enum HobFetchAffected {
 #[derive(Debug)]
 pub enum HobFetchErrorKind {
     VerificationFailed { hashes: Vec<FailedHash> },
+    SignatureFailed { reason: String },
     _Other,
 }
 
+pub enum Hasher {
+    Ring(Context),
+    Blake2b(Blake2b512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => Hasher::Ring(Context::new(&SHA256)),
+            ChecksumAlgorithm::Sha512 => Hasher::Ring(Context::new(&SHA512)),
+            ChecksumAlgorithm::Blake2b => Hasher::Blake2b(Blake2b512::new()),
+            ChecksumAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Ring(ctx) => ctx.update(data),
+            Hasher::Blake2b(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    pub fn finish(self) -> Box<[u8]> {
+        match self {
+            Hasher::Ring(ctx) => Box::from(ctx.finish().as_ref()),
+            Hasher::Blake2b(hasher) => Box::from(hasher.finalize().as_slice()),
+            Hasher::Blake3(hasher) => Box::from(hasher.finalize().as_bytes().as_slice()),
+        }
+    }
+}
+
 pub struct DigestPool<'a> {
-    pool: Vec<(Context, &'a [u8], &'static str)>,
+    pool: Vec<(Hasher, &'a [u8], &'static str)>,
 }
 
 impl DigestPool<'_> {
     pub fn from_verification(verification: &Verification) -> DigestPool {
-        let mut pool = vec![];
+        let pool = verification
+            .checksums
+            .iter()
+            .map(|sum| {
+                (
+                    Hasher::new(sum.algorithm),
+                    sum.digest.as_slice(),
+                    sum.algorithm.name(),
+                )
+            })
+            .collect();
 
-        if let Some(sha) = &verification.sha256 {
-            pool.push((Context::new(&SHA256), &sha[..], "sha256"));
-        }
+        DigestPool { pool }
+    }
+
+    /// A pool that only computes digests, see [`DigestPool::digests`].
+    pub fn for_algorithms(algorithms: &[ChecksumAlgorithm]) -> DigestPool<'static> {
+        let pool = algorithms
+            .iter()
+            .map(|algorithm| (Hasher::new(*algorithm), &[][..], algorithm.name()))
+            .collect();
 
         DigestPool { pool }
     }
 
     pub fn update(&mut self, data: &[u8]) {
-        for (ctx, _, _) in &mut self.pool {
-            ctx.update(data);
+        for (hasher, _, _) in &mut self.pool {
+            hasher.update(data);
         }
     }
 
     pub fn finish(self) -> Result<(), Vec<FailedHash>> {
         let mut failed_hash = vec![];
 
-        for (ctx, comp, algo) in self.pool {
-            let dig = ctx.finish();
+        for (hasher, comp, algo) in self.pool {
+            let dig = hasher.finish();
             if dig.as_ref() != comp {
                 failed_hash.push(FailedHash {
                     algo,
-                    found: Box::from(dig.as_ref()),
+                    found: dig,
                     expected: Box::from(comp),
                 });
             }
@@ -143,61 +241,208 @@ impl DigestPool<'_> {
             Err(failed_hash)
         }
     }
+
+    /// Finishes all hashes without comparing them, returning the digests by algorithm name.
+    pub fn digests(self) -> Vec<(&'static str, Box<[u8]>)> {
+        self.pool
+            .into_iter()
+            .map(|(hasher, _, algo)| (algo, hasher.finish()))
+            .collect()
+    }
 }
 
 impl Fetcher {
     pub fn new(settings: Arc<EngineSettings>) -> Self {
+        let http_client = Client::builder()
+            .connect_timeout(settings.fetch_timeout)
+            .build()
+            .expect("failed to create http client");
+
         Fetcher {
             settings,
-            http_client: Client::new(),
+            http_client,
+            mirror_locks: Mutex::default(),
         }
     }
 
-    pub async fn fetch<'a>(&self, artifact: &'a Artifact) -> anyhow::Result<FetchedArtifact<'a>> {
+    pub async fn fetch<'a>(
+        &self,
+        recipe: &Recipe,
+        artifact: &'a Artifact,
+    ) -> anyhow::Result<FetchedArtifact<'a>> {
+        if self.settings.strict && !is_pinned(artifact) {
+            bail!(
+                "{} of {} has no checksum or signature, which strict mode requires (see `hob checksum`)",
+                artifact.file_name(),
+                recipe.name
+            );
+        }
+
+        self.fetch_allow_unverified(recipe, artifact).await
+    }
+
+    /// Fetches an artifact like [`Fetcher::fetch`], but also in strict mode when the artifact
+    /// has nothing to verify it with.
+    pub async fn fetch_allow_unverified<'a>(
+        &self,
+        recipe: &Recipe,
+        artifact: &'a Artifact,
+    ) -> anyhow::Result<FetchedArtifact<'a>> {
         let path = self.create_artifact_path(artifact);
-        if tokio::fs::metadata(&path)
-            .await
-            .map(|_| true)
-            .or_else(|e| {
-                if e.kind() == ErrorKind::NotFound {
-                    Ok(false)
-                } else {
-                    Err(e)
-                }
-            })?
-        {
-            return if let Err(hashes) = self
-                .verify_file(path.as_path(), &artifact.verification)
+        if let ArtifactSource::Git(git) = &artifact.source {
+            if self.settings.offline && git.submodules {
+                bail!(
+                    "submodules of {} are cloned when it is extracted, which hob can't do offline",
+                    git.url
+                );
+            }
+
+            let lock = self
+                .mirror_locks
+                .lock()
+                .unwrap()
+                .entry(path.clone())
+                .or_default()
+                .clone();
+            let _guard = lock.lock().await;
+
+            let commit = if self.settings.offline {
+                GitMirror::new(&path)
+                    .resolve(git)
+                    .await?
+                    .ok_or_else(|| offline_error(&git.url, &path))?
+            } else {
+                let (mirror, fresh) = GitMirror::open_or_clone(&git.url, &path).await?;
+                mirror.resolve_or_update(git, fresh).await?
+            };
+
+            return Ok(FetchedArtifact {
+                artifact,
+                path,
+                commit: Some(commit),
+            });
+        }
+
+        let affected = self.fetch_checked(recipe, artifact, &path).await?;
+
+        if let Some(signature) = &artifact.verification.signature {
+            if let Err(reason) = self
+                .check_signature(recipe, artifact, signature, &path)
                 .await?
             {
+                if let HobFetchAffected::Fetched = affected {
+                    tokio::fs::remove_file(&path).await?;
+                }
+
+                return Err(HobFetchError {
+                    kind: HobFetchErrorKind::SignatureFailed { reason },
+                    affected: Some(affected),
+                    artifact: artifact.clone(),
+                    _inner: None,
+                }
+                .into());
+            }
+        }
+
+        Ok(FetchedArtifact {
+            artifact,
+            path,
+            commit: None,
+        })
+    }
+
+    /// Makes sure the artifact is in the cache at `path` and matches its checksums, returning
+    /// whether it was already cached or had to be fetched.
+    async fn fetch_checked(
+        &self,
+        recipe: &Recipe,
+        artifact: &Artifact,
+        path: &Path,
+    ) -> anyhow::Result<HobFetchAffected> {
+        if tokio::fs::metadata(path).await.map(|_| true).or_else(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                Ok(false)
+            } else {
+                Err(e)
+            }
+        })? {
+            return if let Err(hashes) = self.verify_file(path, &artifact.verification).await? {
                 Err(HobFetchError {
                     kind: HobFetchErrorKind::VerificationFailed { hashes },
-                    affected: Some(HobFetchAffected::Cache(path)),
+                    affected: Some(HobFetchAffected::Cache(path.to_path_buf())),
                     artifact: artifact.clone(),
                     _inner: None,
                 }
                 .into())
             } else {
-                Ok(FetchedArtifact { artifact, path })
+                Ok(HobFetchAffected::Cache(path.to_path_buf()))
             };
         };
 
         return match &artifact.source {
+            ArtifactSource::Fetch(fetch) if self.settings.offline => {
+                Err(offline_error(&fetch.url, path))
+            }
+
             ArtifactSource::Fetch(fetch) => {
-                let req = self.http_client.get(&fetch.url).build()?;
-                let mut resp = self.http_client.execute(req).await?;
+                let partial = partial_path(path);
+                let mut errors = vec![];
+
+                if self
+                    .fetch_from_cache_server(artifact, path, &mut errors)
+                    .await?
+                {
+                    return Ok(HobFetchAffected::Fetched);
+                }
 
-                let mut f = File::create(&path).await?;
-                let mut pool = DigestPool::from_verification(&artifact.verification);
+                for url in self.candidate_urls(fetch) {
+                    let pool = self
+                        .download_with_retries(&url, &partial, &artifact.verification, &mut errors)
+                        .await;
+
+                    match pool.map(DigestPool::finish) {
+                        Some(Ok(())) => {
+                            tokio::fs::rename(&partial, path).await?;
+                            return Ok(HobFetchAffected::Fetched);
+                        }
+
+                        Some(Err(hashes)) => errors.push(
+                            HobFetchError {
+                                kind: HobFetchErrorKind::VerificationFailed { hashes },
+                                affected: Some(HobFetchAffected::Fetched),
+                                artifact: artifact.clone(),
+                                _inner: None,
+                            }
+                            .into(),
+                        ),
+
+                        None => {}
+                    }
 
-                while let Some(chunk) = resp.chunk().await? {
-                    pool.update(&chunk);
-                    f.write(&chunk).await?;
+                    // never continue a download of one url with the data of another
+                    match tokio::fs::remove_file(&partial).await {
+                        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    }
                 }
 
-                if let Err(hashes) = pool.finish() {
-                    drop(f);
-                    tokio::fs::remove_file(&path).await?;
+                Err(EngineError { errors }.into())
+            }
+
+            ArtifactSource::File(_) | ArtifactSource::Patch(_) => {
+                let local = artifact.source.local().unwrap();
+                let source = local.resolve(recipe).ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "{} can only be used by recipes loaded from a file",
+                        local.path
+                    )
+                })?;
+
+                let partial = partial_path(path);
+                tokio::fs::copy(&source, &partial).await?;
+
+                if let Err(hashes) = self.verify_file(&partial, &artifact.verification).await? {
+                    tokio::fs::remove_file(&partial).await?;
 
                     return Err(HobFetchError {
                         kind: HobFetchErrorKind::VerificationFailed { hashes },
@@ -208,15 +453,261 @@ impl Fetcher {
                     .into());
                 }
 
-                f.sync_all().await?;
+                tokio::fs::rename(&partial, path).await?;
+
+                Ok(HobFetchAffected::Fetched)
+            }
+
+            ArtifactSource::Git(_) => unreachable!("git artifacts are mirrored above"),
+        };
+    }
+
+    /// Asks the cache server for the artifact cached at `path`, returning whether it was fetched
+    /// from there.
+    ///
+    /// Cache servers use the layout of the local cache, so they're asked for the name the
+    /// artifact is cached at. They're only a shortcut, so there is a single attempt and the data
+    /// goes to a partial file of its own, which leaves an interrupted download from the urls of
+    /// the artifact to be resumed.
+    async fn fetch_from_cache_server(
+        &self,
+        artifact: &Artifact,
+        path: &Path,
+        errors: &mut Vec<anyhow::Error>,
+    ) -> anyhow::Result<bool> {
+        let server = match &self.settings.cache_server {
+            Some(server) => server,
+            None => return Ok(false),
+        };
+
+        let url = format!(
+            "{}/{}",
+            server.trim_end_matches('/'),
+            path.file_name().unwrap_or_default().to_string_lossy()
+        );
+
+        let mut partial = path.as_os_str().to_os_string();
+        partial.push(".cache-server.part");
+        let partial = PathBuf::from(partial);
+
+        match self.download(&url, &partial, &artifact.verification).await {
+            Ok(pool) => match pool.finish() {
+                Ok(()) => {
+                    tokio::fs::rename(&partial, path).await?;
+                    return Ok(true);
+                }
+
+                Err(hashes) => errors.push(
+                    HobFetchError {
+                        kind: HobFetchErrorKind::VerificationFailed { hashes },
+                        affected: Some(HobFetchAffected::Fetched),
+                        artifact: artifact.clone(),
+                        _inner: None,
+                    }
+                    .into(),
+                ),
+            },
+
+            Err(e) => errors.push(e.context(format!("cache server {} failed", url))),
+        }
+
+        match tokio::fs::remove_file(&partial).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(false),
+        }
+    }
+
+    /// The urls of the artifact and the configured mirrors, in that order.
+    fn candidate_urls(&self, fetch: &FetchArtifact) -> Vec<String> {
+        let mirrors = self
+            .settings
+            .mirrors
+            .iter()
+            .map(|mirror| format!("{}/{}", mirror.trim_end_matches('/'), fetch.file_name()));
 
-                Ok(FetchedArtifact { artifact, path })
+        fetch.urls().map(str::to_string).chain(mirrors).collect()
+    }
+
+    /// Downloads `url` into `partial`, retrying with a growing delay between attempts.
+    ///
+    /// Every failed attempt is added to `errors`, client errors like a 404 are not retried.
+    async fn download_with_retries<'v>(
+        &self,
+        url: &str,
+        partial: &Path,
+        verification: &'v Verification,
+        errors: &mut Vec<anyhow::Error>,
+    ) -> Option<DigestPool<'v>> {
+        for attempt in 0..=self.settings.fetch_retries {
+            if attempt > 0 {
+                let delay = Duration::from_secs(1 << min(attempt - 1, 5));
+                println!("retrying {} in {}s", url, delay.as_secs());
+                tokio::time::sleep(delay).await;
+            }
+
+            match self.download(url, partial, verification).await {
+                Ok(pool) => return Some(pool),
+                Err(e) => {
+                    let client_error = e
+                        .downcast_ref::<reqwest::Error>()
+                        .and_then(reqwest::Error::status)
+                        .is_some_and(|status| status.is_client_error());
+
+                    errors.push(e.context(format!("attempt {} of {} failed", attempt + 1, url)));
+
+                    if client_error {
+                        break;
+                    }
+                }
             }
+        }
+
+        None
+    }
+
+    /// Downloads `url` into `partial`, continuing a previously interrupted download with a range
+    /// request when the server supports it.
+    ///
+    /// Returns a pool that has seen the whole file, including any part downloaded before.
+    async fn download<'v>(
+        &self,
+        url: &str,
+        partial: &Path,
+        verification: &'v Verification,
+    ) -> anyhow::Result<DigestPool<'v>> {
+        let offset = match tokio::fs::metadata(partial).await {
+            Ok(md) => md.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut req = self.http_client.get(url);
+        if offset > 0 {
+            req = req.header(RANGE, format!("bytes={}-", offset));
+        }
+
+        let mut resp = self.with_timeout(req.send()).await??;
+        if resp.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            // the partial file doesn't fit what the server has (anymore), start over
+            resp = self
+                .with_timeout(self.http_client.get(url).send())
+                .await??;
+        }
+
+        let mut resp = resp.error_for_status()?;
+        let mut pool = DigestPool::from_verification(verification);
+
+        // servers without range support answer with the whole file instead
+        let mut f = if offset > 0 && resp.status() == StatusCode::PARTIAL_CONTENT {
+            println!("resuming download of {} at {} bytes", url, offset);
+            read_into_pool(partial, &mut pool).await?;
+            OpenOptions::new().append(true).open(partial).await?
+        } else {
+            File::create(partial).await?
         };
+
+        while let Some(chunk) = self.with_timeout(resp.chunk()).await?? {
+            pool.update(&chunk);
+            f.write_all(&chunk).await?;
+        }
+
+        f.sync_all().await?;
+
+        Ok(pool)
+    }
+
+    /// Fails when `fut` makes no progress within the configured fetch timeout.
+    async fn with_timeout<T>(&self, fut: impl Future<Output = T>) -> anyhow::Result<T> {
+        tokio::time::timeout(self.settings.fetch_timeout, fut)
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "no progress for {} seconds",
+                    self.settings.fetch_timeout.as_secs()
+                )
+            })
     }
 
-    fn create_artifact_path(&self, artifact: &Artifact) -> PathBuf {
-        let name = artifact.file_name();
+    /// Fetches the detached signature of the artifact into the cache, next to the artifact, and
+    /// checks it against the key in the recipe directory.
+    async fn check_signature(
+        &self,
+        recipe: &Recipe,
+        artifact: &Artifact,
+        signature: &Signature,
+        path: &Path,
+    ) -> anyhow::Result<Result<(), String>> {
+        let sig_path = signature_path(path, signature);
+        let extension = signature.kind.extension();
+
+        // fetched like any other artifact, next to the urls and mirrors of the artifact itself
+        let urls: Vec<String> = match (&signature.url, &artifact.source) {
+            (Some(url), _) => vec![url.clone()],
+            (None, ArtifactSource::Fetch(fetch)) => fetch
+                .urls()
+                .map(|url| format!("{}.{}", url, extension))
+                .collect(),
+            (None, _) => bail!("signature of {} requires an url", artifact.file_name()),
+        };
+        let mut urls = urls.into_iter();
+
+        let sig_artifact = Artifact {
+            source: ArtifactSource::Fetch(FetchArtifact {
+                url: urls.next().unwrap(),
+                mirrors: urls.collect(),
+                file_name: format!("{}.{}", artifact.file_name(), extension),
+                format: None,
+            }),
+            verification: Verification::default(),
+            extraction: Extraction::default(),
+        };
+        self.fetch_checked(recipe, &sig_artifact, &sig_path).await?;
+
+        let key = recipe
+            .directory()
+            .map(|dir| dir.join(&signature.key))
+            .ok_or_else(|| {
+                anyhow!("signature keys can only be used by recipes loaded from a file")
+            })?;
+
+        let res = verify_signature(signature.kind, &key, &sig_path, path).await?;
+        if res.is_err() {
+            // a bad signature may have been replaced upstream, fetch it again next time
+            tokio::fs::remove_file(&sig_path).await?;
+        }
+
+        Ok(res)
+    }
+
+    /// Returns the cache entries the artifact needs that are not in the cache yet.
+    pub async fn missing_from_cache(&self, artifact: &Artifact) -> Vec<PathBuf> {
+        let path = self.create_artifact_path(artifact);
+        let mut needed = match &artifact.source {
+            ArtifactSource::Fetch(_) | ArtifactSource::Git(_) => vec![path.clone()],
+            ArtifactSource::File(_) | ArtifactSource::Patch(_) => vec![],
+        };
+
+        if let Some(signature) = &artifact.verification.signature {
+            needed.push(signature_path(&path, signature));
+        }
+
+        let mut missing = vec![];
+        for path in needed {
+            if tokio::fs::metadata(&path).await.is_err() {
+                missing.push(path);
+            }
+        }
+
+        missing
+    }
+
+    /// Path the artifact is cached at, named after its [`Artifact::hash_id`] and file name.
+    pub fn create_artifact_path(&self, artifact: &Artifact) -> PathBuf {
+        let name = match &artifact.source {
+            // one mirror per repository, whatever the checkouts are called
+            ArtifactSource::Git(git) => git.mirror_name(),
+            _ => artifact.file_name(),
+        };
         let hash = artifact.hash_id();
         let file_name = format!("{}-{}", hash.encode_hex::<String>(), name);
 
@@ -228,25 +719,77 @@ impl Fetcher {
         path: &Path,
         verification: &Verification,
     ) -> anyhow::Result<Result<(), Vec<FailedHash>>> {
-        let mut file = OpenOptions::new()
-            .read(true)
-            .create(false)
-            .write(false)
-            .open(path)
-            .await?;
-
         let mut pool = DigestPool::from_verification(verification);
-        let mut buffer = vec![0; 4096];
+        read_into_pool(path, &mut pool).await?;
 
-        loop {
-            let r = file.read(&mut buffer).await?;
-            if r == 0 {
-                break;
-            }
+        Ok(pool.finish())
+    }
+
+    pub async fn compute_digests(
+        &self,
+        path: &Path,
+        algorithms: &[ChecksumAlgorithm],
+    ) -> anyhow::Result<Vec<(&'static str, Box<[u8]>)>> {
+        let mut pool = DigestPool::for_algorithms(algorithms);
+        read_into_pool(path, &mut pool).await?;
 
-            pool.update(&buffer[..r]);
+        Ok(pool.digests())
+    }
+}
+
+async fn read_into_pool(path: &Path, pool: &mut DigestPool<'_>) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .create(false)
+        .write(false)
+        .open(path)
+        .await?;
+
+    let mut buffer = vec![0; 4096];
+
+    loop {
+        let r = file.read(&mut buffer).await?;
+        if r == 0 {
+            break;
         }
 
-        Ok(pool.finish())
+        pool.update(&buffer[..r]);
+    }
+
+    Ok(())
+}
+
+/// Path the detached signature of the artifact cached at `path` is kept at.
+fn signature_path(path: &Path, signature: &Signature) -> PathBuf {
+    let mut sig_path = path.as_os_str().to_os_string();
+    sig_path.push(".");
+    sig_path.push(signature.kind.extension());
+    PathBuf::from(sig_path)
+}
+
+fn offline_error(what: &str, path: &Path) -> anyhow::Error {
+    anyhow!(
+        "{} is not in the cache as {} and hob is offline",
+        what,
+        path.file_name().unwrap_or_default().to_string_lossy()
+    )
+}
+
+/// Path downloads are written to before they are verified and moved to `path`.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_os_string();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+/// Whether the contents of an artifact are pinned, either by verification or because they
+/// come from the recipe repository or a fixed git commit.
+fn is_pinned(artifact: &Artifact) -> bool {
+    match &artifact.source {
+        ArtifactSource::File(_) | ArtifactSource::Patch(_) => true,
+        ArtifactSource::Git(git) => matches!(git.reference, GitReference::Rev(_)),
+        ArtifactSource::Fetch(_) => {
+            !artifact.verification.checksums.is_empty() || artifact.verification.signature.is_some()
+        }
     }
 }
@@ -0,0 +1,178 @@
+use crate::definition::{GitArtifact, GitReference};
+use anyhow::{anyhow, bail};
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// A bare mirror of a remote repository, kept in the cache.
+#[derive(Debug)]
+pub struct GitMirror {
+    path: PathBuf,
+}
+
+impl GitMirror {
+    /// Opens the mirror at `path`, cloning it from `url` first if it doesn't exist yet.
+    ///
+    /// The returned bool is true if the mirror was cloned just now.
+    pub async fn open_or_clone(url: &str, path: &Path) -> anyhow::Result<(GitMirror, bool)> {
+        match tokio::fs::metadata(path).await {
+            Ok(_) => return Ok((GitMirror::new(path), false)),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // clone next to the final location, so an interrupted clone is never mistaken for a
+        // complete mirror
+        let mut partial = path.as_os_str().to_os_string();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+        if tokio::fs::metadata(&partial).await.is_ok() {
+            tokio::fs::remove_dir_all(&partial).await?;
+        }
+
+        run_git(
+            None,
+            [
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--mirror"),
+                OsStr::new(url),
+                partial.as_os_str(),
+            ],
+        )
+        .await?;
+
+        tokio::fs::rename(&partial, path).await?;
+
+        Ok((GitMirror::new(path), true))
+    }
+
+    pub fn new(path: &Path) -> Self {
+        GitMirror {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Fetches all refs from the remote, dropping the ones that were removed upstream.
+    pub async fn update(&self) -> anyhow::Result<()> {
+        self.git(["remote", "update", "--prune"]).await?;
+
+        Ok(())
+    }
+
+    /// Resolves the reference of the artifact to a full commit hash, if the mirror has it.
+    pub async fn resolve(&self, artifact: &GitArtifact) -> anyhow::Result<Option<String>> {
+        let spec = artifact.reference.rev_spec();
+        match self.git(["rev-parse", "--verify", "--quiet", &spec]).await {
+            Ok(commit) => Ok(Some(commit.trim().to_string())),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Resolves the artifact to a commit, updating the mirror when the reference is missing.
+    ///
+    /// Branches move, so unless the mirror was just cloned it is always updated for them.
+    pub async fn resolve_or_update(
+        &self,
+        artifact: &GitArtifact,
+        fresh: bool,
+    ) -> anyhow::Result<String> {
+        let moving = matches!(artifact.reference, GitReference::Branch(_));
+        if !moving || fresh {
+            if let Some(commit) = self.resolve(artifact).await? {
+                return Ok(commit);
+            }
+        }
+
+        if !fresh {
+            self.update().await?;
+            if let Some(commit) = self.resolve(artifact).await? {
+                return Ok(commit);
+            }
+        }
+
+        Err(anyhow!(
+            "{} {} not found in {}",
+            artifact.reference.kind(),
+            artifact.reference.name(),
+            artifact.url
+        ))
+    }
+
+    /// Checks out `commit` into `dest` as a standalone repository, replacing anything that was
+    /// there before.
+    pub async fn checkout(
+        &self,
+        artifact: &GitArtifact,
+        commit: &str,
+        dest: &Path,
+    ) -> anyhow::Result<()> {
+        if tokio::fs::metadata(dest).await.is_ok() {
+            tokio::fs::remove_dir_all(dest).await?;
+        }
+
+        run_git(
+            None,
+            [
+                OsStr::new("clone"),
+                OsStr::new("--quiet"),
+                OsStr::new("--no-checkout"),
+                self.path.as_os_str(),
+                dest.as_os_str(),
+            ],
+        )
+        .await?;
+
+        run_git(Some(dest), ["checkout", "--quiet", "--detach", commit]).await?;
+
+        // point origin at upstream so relative submodule urls resolve against it
+        run_git(Some(dest), ["remote", "set-url", "origin", &artifact.url]).await?;
+
+        if artifact.submodules {
+            run_git(
+                Some(dest),
+                ["submodule", "update", "--quiet", "--init", "--recursive"],
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn git<I, S>(&self, args: I) -> anyhow::Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut all_args = vec![OsStr::new("--git-dir").to_os_string()];
+        all_args.push(self.path.as_os_str().to_os_string());
+        all_args.extend(args.into_iter().map(|x| x.as_ref().to_os_string()));
+
+        run_git(None, all_args).await
+    }
+}
+
+/// Runs git, returning its stdout if it exited successfully.
+async fn run_git<I, S>(dir: Option<&Path>, args: I) -> anyhow::Result<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut cmd = Command::new("git");
+    cmd.args(args).env("GIT_TERMINAL_PROMPT", "0");
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+
+    let output = cmd.output().await?;
+    if !output.status.success() {
+        bail!(
+            "git failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,63 @@
+use crate::definition::actions::Stage;
+use crate::engine::build_state::BuildState;
+use crate::engine::hooks::{Hook, HookTrigger};
+use crate::Engine;
+use async_trait::async_trait;
+use miette::Diagnostic;
+use thiserror::Error;
+
+#[derive(Debug)]
+pub struct CheckSourceDir;
+
+#[derive(Debug, Diagnostic, Error)]
+#[error("source directory {source_dir} of {recipe} doesn't exist after extracting, found: {}", .found.join(", "))]
+#[diagnostic(help(
+    "set `source-dir` in the recipe to the directory the sources were extracted to"
+))]
+pub struct HobSourceDirError {
+    pub recipe: String,
+    pub source_dir: String,
+    pub found: Vec<String>,
+}
+
+#[async_trait]
+impl Hook for CheckSourceDir {
+    const PRIORITY: usize = 10;
+    const TRIGGER: HookTrigger = HookTrigger::After;
+    const STAGE: Stage = Stage::Extract;
+
+    async fn run(&self, state: &mut BuildState, engine: &Engine) -> anyhow::Result<()> {
+        let source_dir = engine
+            .settings
+            .extracted_source_path_for_recipe(state.recipe);
+
+        if tokio::fs::metadata(&source_dir)
+            .await
+            .is_ok_and(|x| x.is_dir())
+        {
+            return Ok(());
+        }
+
+        let mut found = vec![];
+        let mut dir =
+            tokio::fs::read_dir(engine.settings.source_path_for_recipe(state.recipe)).await?;
+
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                found.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        if found.is_empty() {
+            found.push("nothing".to_string());
+        }
+        found.sort();
+
+        Err(HobSourceDirError {
+            recipe: state.recipe.name.clone(),
+            source_dir: state.recipe.source_dir.clone(),
+            found,
+        }
+        .into())
+    }
+}
@@ -0,0 +1,5 @@
+pub mod check_source_dir;
+pub mod source_manifest;
+
+pub use check_source_dir::*;
+pub use source_manifest::*;
@@ -0,0 +1,88 @@
+use crate::definition::actions::Stage;
+use crate::engine::build_state::{BuildState, SourceFile};
+use crate::engine::hooks::{Hook, HookTrigger};
+use crate::utils::FileWalker;
+use crate::Engine;
+use async_trait::async_trait;
+use ring::digest::{Context, SHA256};
+use std::os::unix::fs::PermissionsExt;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+#[derive(Debug)]
+pub struct SourceManifest;
+
+#[async_trait]
+impl Hook for SourceManifest {
+    const PRIORITY: usize = 0;
+    const TRIGGER: HookTrigger = HookTrigger::After;
+    const STAGE: Stage = Stage::Extract;
+
+    async fn run(&self, state: &mut BuildState, engine: &Engine) -> anyhow::Result<()> {
+        let root = engine.settings.source_path_for_recipe(state.recipe);
+        let mut files = FileWalker::new(&root).await?;
+        let mut manifest = vec![];
+
+        while let Some(entry) = files.next().await? {
+            let path = entry.path();
+            let metadata = tokio::fs::symlink_metadata(&path).await?;
+
+            // symlinks are recorded without following them, they may point anywhere
+            let sha256 = if metadata.is_file() {
+                Some(hash_file(&mut File::open(&path).await?).await?)
+            } else {
+                None
+            };
+
+            manifest.push(SourceFile {
+                path: path.strip_prefix(&root)?.to_path_buf(),
+                size: metadata.len(),
+                mode: metadata.permissions().mode(),
+                sha256,
+            });
+        }
+
+        manifest.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let mut listing = String::new();
+        for file in &manifest {
+            listing.push_str(&format!(
+                "{:06o} {} {} {}\n",
+                file.mode,
+                file.size,
+                file.sha256.map_or_else(|| "-".to_string(), hex::encode),
+                file.path.display()
+            ));
+        }
+        tokio::fs::write(
+            engine.settings.manifest_path_for_recipe(state.recipe),
+            listing,
+        )
+        .await?;
+
+        println!(
+            "[{}]     recorded {} extracted files",
+            state.recipe.name,
+            manifest.len()
+        );
+        state.source_manifest = manifest;
+
+        Ok(())
+    }
+}
+
+async fn hash_file(file: &mut File) -> anyhow::Result<[u8; 32]> {
+    let mut digest = Context::new(&SHA256);
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+
+        digest.update(&buffer[..read]);
+    }
+
+    Ok(digest.finish().as_ref().try_into()?)
+}
@@ -1,3 +1,4 @@
+pub mod extract;
 pub mod install;
 pub mod package;
 
@@ -25,6 +26,8 @@ pub enum HookTrigger {
 type HookRef = &'static dyn HookVTable;
 
 const HOOKS: &[HookRef] = &[
+    &extract::SourceManifest,
+    &extract::CheckSourceDir,
     &install::CollectElf,
     &install::StripBinaries,
     &package::PinTimestamps,
@@ -49,7 +49,9 @@ impl Hook for PinTimestamps {
         files.push(path).await?;
 
         for side in &state.recipe.sides {
-            files.push(engine.settings.dest_path_for_side(side)).await?;
+            files
+                .push(engine.settings.dest_path_for_side(state.recipe, side))
+                .await?;
         }
 
         while let Some(file) = files.next().await? {
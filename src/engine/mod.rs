@@ -1,34 +1,47 @@
 use crate::definition::actions::Stage;
-use crate::definition::Side;
+use crate::definition::{ArtifactSource, Side};
 use crate::engine::build_state::BuildState;
 use crate::engine::environment::Environment;
 use crate::engine::extractor::Extractor;
-use crate::engine::fetcher::Fetcher;
+use crate::engine::fetcher::{FetchedArtifact, Fetcher, HobOfflineError};
 use crate::engine::hooks::{HookTrigger, SORTED_HOOKS};
-use crate::engine::packager::{Packager, PackagerBuilder};
+use crate::engine::packager::{Packager, PackagerType};
+use crate::engine::patcher::Patcher;
 use crate::engine::player::{Context, Player};
 use crate::Recipe;
 use futures::future::join_all;
 use futures::FutureExt;
+use ring::digest::{Context as DigestContext, SHA256};
 use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
+mod archives;
 mod build_state;
 pub mod build_style;
+pub mod cache;
+mod cache_server;
+mod checksum;
+pub mod config;
 mod environment;
 mod extractor;
 mod fetcher;
+mod git;
 mod hooks;
 pub mod packager;
+mod patcher;
 mod player;
+mod scheduler;
+mod signature;
 
 #[derive(Debug)]
 pub struct Engine {
     fetcher: Fetcher,
     extractor: Extractor,
+    patcher: Patcher,
     player: Player,
     environment: Environment,
     packager: Box<dyn Packager>,
@@ -43,6 +56,16 @@ pub enum ChrootMethod {
     _Ethereal,
 }
 
+impl ChrootMethod {
+    pub fn parse<T: AsRef<str>>(data: T) -> Option<ChrootMethod> {
+        Some(match data.as_ref() {
+            "none" => ChrootMethod::None,
+            "chroot" => ChrootMethod::SystemChroot,
+            _ => return None,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct EngineSettings {
     cache_path: PathBuf,
@@ -51,6 +74,20 @@ pub struct EngineSettings {
     dest_path: PathBuf,
     package_path: PathBuf,
     chroot_method: ChrootMethod,
+    packager: PackagerType,
+    jobs: usize,
+    cpus: usize,
+    /// Refuse artifacts that have neither a checksum nor a signature.
+    strict: bool,
+    /// Never use the network, only artifacts that are already in the cache.
+    offline: bool,
+    /// Url prefixes to fetch artifacts from, by file name, after their own urls failed.
+    mirrors: Vec<String>,
+    /// `hob serve-cache` instance asked for artifacts before their own urls.
+    cache_server: Option<String>,
+    fetch_retries: usize,
+    /// How long a download may go without progress before it is retried.
+    fetch_timeout: Duration,
 }
 
 impl EngineSettings {
@@ -62,16 +99,21 @@ impl EngineSettings {
         self.root_path.join(&self.dest_path)
     }
 
+    pub fn dest_path_for_build(&self, recipe: &Recipe) -> PathBuf {
+        self.dest_path().join(recipe.build_key())
+    }
+
     pub fn dest_path_for_context(&self, context: Context) -> PathBuf {
-        self.dest_path().join(context.name())
+        self.dest_path_for_build(context.recipe())
+            .join(context.name())
     }
 
     pub fn dest_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
-        self.dest_path().join(&recipe.name)
+        self.dest_path_for_build(recipe).join(&recipe.name)
     }
 
-    pub fn dest_path_for_side(&self, side: &Side) -> PathBuf {
-        self.dest_path().join(&side.name)
+    pub fn dest_path_for_side(&self, recipe: &Recipe, side: &Side) -> PathBuf {
+        self.dest_path_for_build(recipe).join(&side.name)
     }
 
     pub fn source_path(&self) -> PathBuf {
@@ -79,13 +121,44 @@ impl EngineSettings {
     }
 
     pub fn source_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
-        self.source_path().join(&recipe.name)
+        self.source_path().join(recipe.build_key())
     }
 
     pub fn extracted_source_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
+        self.source_path_for_recipe(recipe).join(&recipe.source_dir)
+    }
+
+    /// Out of tree build directory for build styles that support one, next to the sources so it
+    /// never mixes with a `build` directory of the sources themselves.
+    pub fn build_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
+        self.source_path()
+            .join(format!("{}.build", recipe.build_key()))
+    }
+
+    /// Directory archives are unpacked into before their leading components are stripped, next
+    /// to the sources of a recipe so a failed unpack leaves nothing behind in them.
+    pub fn staging_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
+        self.source_path()
+            .join(format!("{}.staging", recipe.build_key()))
+    }
+
+    /// Listing of the extracted sources of a recipe, next to its source directory.
+    pub fn manifest_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
+        self.source_path()
+            .join(format!("{}.manifest", recipe.build_key()))
+    }
+
+    /// Identifies the artifacts the sources of a recipe were last extracted from, next to them.
+    pub fn stamp_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
+        self.source_path()
+            .join(format!("{}.stamp", recipe.build_key()))
+    }
+
+    /// Directory the patches of a recipe are copied to, next to its sources so they can't collide
+    /// with a `patches` directory of the sources themselves.
+    pub fn patch_path_for_recipe(&self, recipe: &Recipe) -> PathBuf {
         self.source_path()
-            .join(&recipe.name)
-            .join(&recipe.source_dir)
+            .join(format!("{}.patches", recipe.build_key()))
     }
 
     pub fn package_path_for_packager(&self, packager: &str) -> PathBuf {
@@ -120,25 +193,15 @@ impl Display for EngineError {
 }
 
 impl Engine {
-    pub fn new<T: PackagerBuilder>() -> Self {
-        Self::from_settings::<T>(EngineSettings {
-            cache_path: PathBuf::from("/tmp/hob/cache"),
-            source_path: PathBuf::from(".hob/src"),
-            dest_path: PathBuf::from(".hob/dest"),
-            root_path: PathBuf::from("/tmp/hob/root"),
-            package_path: PathBuf::from(".hob/pkg"),
-            chroot_method: ChrootMethod::SystemChroot,
-        })
-    }
-
-    pub fn from_settings<T: PackagerBuilder>(settings: EngineSettings) -> Self {
+    pub fn new(settings: EngineSettings) -> Self {
         let settings = Arc::from(settings);
         Engine {
             fetcher: Fetcher::new(settings.clone()),
             extractor: Extractor::new(settings.clone()),
+            patcher: Patcher::new(settings.clone()),
             player: Player::new(settings.clone()),
             environment: Environment::new(settings.clone()),
-            packager: Box::new(T::build(settings.clone())),
+            packager: settings.packager.build(settings.clone()),
             settings,
         }
     }
@@ -157,32 +220,39 @@ impl Engine {
     ) -> anyhow::Result<()> {
         for hook in SORTED_HOOKS.iter().copied() {
             if hook.when() == (stage, trigger) {
-                println!("    running hook: {:?}", hook);
+                println!("[{}]     running hook: {:?}", state.recipe.name, hook);
                 hook.trigger(state, self).await?;
             }
         }
         Ok(())
     }
 
-    pub async fn build_recipe(&self, recipe: &Recipe) -> anyhow::Result<()> {
+    pub async fn run_stages(
+        &self,
+        recipe: &Recipe,
+        stages: &[Stage],
+        cpus: usize,
+    ) -> anyhow::Result<()> {
         let mut state = BuildState {
             build_time: SystemTime::now(),
             recipe,
+            cpus,
             stage: Stage::Prepare,
             artifacts: vec![],
             elf_headers: Default::default(),
             archives: vec![],
+            source_manifest: vec![],
         };
 
-        for stage in Stage::stages() {
-            println!("running stage: {:?}", stage);
+        for stage in stages.iter().copied() {
+            println!("[{}] running stage: {:?}", recipe.name, stage);
             state.stage = stage;
 
-            println!("  running before hooks");
+            println!("[{}]   running before hooks", recipe.name);
             self.run_hooks(&mut state, stage, HookTrigger::Before)
                 .await?;
 
-            println!("  running action");
+            println!("[{}]   running action", recipe.name);
             match stage {
                 Stage::Prepare => {
                     self.prepare_engine().await?;
@@ -210,6 +280,17 @@ impl Engine {
                         .await?;
                 }
 
+                Stage::Patch => {
+                    self.player
+                        .play(
+                            &mut state,
+                            Context::Recipe(recipe),
+                            &self.environment,
+                            |state, _| async { self.patch(state).await }.boxed(),
+                        )
+                        .await?;
+                }
+
                 Stage::Split => {
                     self.split_claims(recipe).await?;
                 }
@@ -230,7 +311,7 @@ impl Engine {
                 }
             }
 
-            println!("  running after hooks");
+            println!("[{}]   running after hooks", recipe.name);
             self.run_hooks(&mut state, stage, HookTrigger::After)
                 .await?;
         }
@@ -238,6 +319,32 @@ impl Engine {
         Ok(())
     }
 
+    pub async fn clean_recipe(&self, recipe: &Recipe) -> anyhow::Result<()> {
+        remove_dir_if_exists(&self.settings.source_path_for_recipe(recipe)).await?;
+        remove_dir_if_exists(&self.settings.staging_path_for_recipe(recipe)).await?;
+        remove_dir_if_exists(&self.settings.build_path_for_recipe(recipe)).await?;
+        remove_dir_if_exists(&self.settings.patch_path_for_recipe(recipe)).await?;
+        for file in [
+            self.settings.manifest_path_for_recipe(recipe),
+            self.settings.stamp_path_for_recipe(recipe),
+        ] {
+            match tokio::fs::remove_file(file).await {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        remove_dir_if_exists(&self.settings.dest_path_for_build(recipe)).await?;
+
+        Ok(())
+    }
+
+    pub async fn clean_all(&self) -> anyhow::Result<()> {
+        remove_dir_if_exists(&self.settings.source_path()).await?;
+        remove_dir_if_exists(&self.settings.dest_path()).await?;
+
+        Ok(())
+    }
+
     async fn package<'a>(
         &self,
         state: &mut BuildState<'a>,
@@ -246,21 +353,84 @@ impl Engine {
         self.packager.build_package(state.recipe, context).await
     }
 
+    /// Extracts the artifacts into a fresh source directory, so nothing a previous build left in
+    /// there is mistaken for the sources.
+    ///
+    /// The build directory and the installed files are only kept when the artifacts are the same
+    /// as the last time, e.g. an edited patch or a moved git tag starts the build over.
     async fn extract<'a>(&self, state: &mut BuildState<'a>) -> anyhow::Result<()> {
+        let stamp = source_stamp(&state.artifacts);
+        let stamp_path = self.settings.stamp_path_for_recipe(state.recipe);
+        if tokio::fs::read_to_string(&stamp_path).await.ok() != Some(stamp.clone()) {
+            remove_dir_if_exists(&self.settings.build_path_for_recipe(state.recipe)).await?;
+            remove_dir_if_exists(&self.settings.dest_path_for_build(state.recipe)).await?;
+        }
+
+        remove_dir_if_exists(&self.settings.source_path_for_recipe(state.recipe)).await?;
+        remove_dir_if_exists(&self.settings.patch_path_for_recipe(state.recipe)).await?;
+
         for item in &state.artifacts {
-            self.extractor.extract(item, &state.recipe.name).await?;
+            self.extractor.extract(item, state.recipe).await?;
         }
 
+        tokio::fs::write(&stamp_path, stamp).await?;
+
         Ok(())
     }
 
+    /// Applies the patch artifacts of the recipe in the order they are defined in.
+    async fn patch<'a>(&self, state: &mut BuildState<'a>) -> anyhow::Result<()> {
+        for item in &state.artifacts {
+            if let ArtifactSource::Patch(patch) = &item.artifact.source {
+                println!("[{}]     applying {}", state.recipe.name, patch.file.path);
+                self.patcher.apply(state.recipe, patch).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collects every cache entry the recipes are missing, so offline builds fail before
+    /// anything is built instead of at the first recipe that needs the network.
+    pub async fn check_offline_cache(&self, recipes: &[Recipe]) -> Result<(), HobOfflineError> {
+        let mut missing = vec![];
+
+        for recipe in recipes {
+            for artifact in &recipe.artifacts {
+                for path in self.fetcher.missing_from_cache(artifact).await {
+                    missing.push(format!(
+                        "  {}: {} ({})",
+                        recipe.name,
+                        artifact.file_name(),
+                        path.file_name().unwrap_or_default().to_string_lossy()
+                    ));
+                }
+
+                // submodules are cloned from their own remotes when the artifact is extracted
+                if matches!(&artifact.source, ArtifactSource::Git(git) if git.submodules) {
+                    missing.push(format!(
+                        "  {}: {} (submodules, which are never cached)",
+                        recipe.name,
+                        artifact.file_name()
+                    ));
+                }
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(HobOfflineError { missing })
+        }
+    }
+
     async fn fetch<'a>(&self, state: &mut BuildState<'a>) -> anyhow::Result<()> {
         let all_fetch: Vec<_> = join_all(
             state
                 .recipe
                 .artifacts
                 .iter()
-                .map(|art| self.fetcher.fetch(art)),
+                .map(|art| self.fetcher.fetch(state.recipe, art)),
         )
         .await;
 
@@ -286,7 +456,7 @@ impl Engine {
     async fn split_claims(&self, recipe: &Recipe) -> anyhow::Result<()> {
         let dest = self.settings.dest_path_for_recipe(recipe);
         for side in &recipe.sides {
-            let side_path = self.settings.dest_path_for_side(side);
+            let side_path = self.settings.dest_path_for_side(recipe, side);
 
             for claim in &side.claims {
                 let own = claim.clone();
@@ -313,3 +483,20 @@ impl Engine {
         Ok(())
     }
 }
+
+/// Digest over the sources of every artifact, in the order they are extracted in.
+fn source_stamp(artifacts: &[FetchedArtifact]) -> String {
+    let mut digest = DigestContext::new(&SHA256);
+    for artifact in artifacts {
+        digest.update(&artifact.source_id());
+    }
+
+    hex::encode(digest.finish())
+}
+
+async fn remove_dir_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_dir_all(path).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
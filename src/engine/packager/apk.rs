@@ -38,10 +38,7 @@ impl Packager for Apk {
         let mut args = ApkArgs::new();
 
         args.info("name", &context.name())
-            .info(
-                "version",
-                &format!("{}-r{}", recipe.version, recipe.revision),
-            )
+            .info("version", recipe.package_version())
             .info("description", context.description())
             .info("license", recipe.license.join(" "))
             .info("origin", context.origin())
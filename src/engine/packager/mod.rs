@@ -18,6 +18,26 @@ pub trait Packager: Send + Sync + Debug {
     ) -> anyhow::Result<()>;
 }
 
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum PackagerType {
+    Apk,
+}
+
+impl PackagerType {
+    pub fn parse<T: AsRef<str>>(data: T) -> Option<PackagerType> {
+        Some(match data.as_ref() {
+            "apk" => PackagerType::Apk,
+            _ => return None,
+        })
+    }
+
+    pub fn build(self, settings: Arc<EngineSettings>) -> Box<dyn Packager> {
+        match self {
+            PackagerType::Apk => Box::new(Apk::build(settings)),
+        }
+    }
+}
+
 pub trait PackagerBuilder {
     type Output: Packager + 'static;
 
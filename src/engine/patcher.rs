@@ -0,0 +1,136 @@
+use crate::definition::PatchArtifact;
+use crate::engine::EngineSettings;
+use crate::Recipe;
+use miette::Diagnostic;
+use std::path::Path;
+use std::process::Output;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::process::Command;
+
+#[derive(Debug)]
+pub struct Patcher {
+    settings: Arc<EngineSettings>,
+}
+
+#[derive(Debug, Diagnostic, Error)]
+#[error("patch {patch} does not apply: {}", .rejected.join(", "))]
+#[diagnostic(help(
+    "refresh the patch against the extracted sources, or change its strip level if the paths don't match"
+))]
+pub struct HobPatchError {
+    pub patch: String,
+    pub rejected: Vec<String>,
+}
+
+impl Patcher {
+    pub fn new(settings: Arc<EngineSettings>) -> Self {
+        Patcher { settings }
+    }
+
+    /// Applies a single patch to the extracted sources of `recipe`.
+    ///
+    /// The patch is tried with `--dry-run` first, so a patch that doesn't apply cleanly never
+    /// leaves the sources half patched.
+    pub async fn apply(&self, recipe: &Recipe, patch: &PatchArtifact) -> anyhow::Result<()> {
+        let file = self
+            .settings
+            .patch_path_for_recipe(recipe)
+            .join(patch.file.file_name());
+
+        let dry_run = self.run_patch(recipe, patch, &file, true).await?;
+        if !dry_run.status.success() {
+            return Err(HobPatchError {
+                patch: patch.file.path.clone(),
+                rejected: rejected_hunks(&dry_run),
+            }
+            .into());
+        }
+
+        let output = self.run_patch(recipe, patch, &file, false).await?;
+        if !output.status.success() {
+            return Err(HobPatchError {
+                patch: patch.file.path.clone(),
+                rejected: rejected_hunks(&output),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    async fn run_patch(
+        &self,
+        recipe: &Recipe,
+        patch: &PatchArtifact,
+        file: &Path,
+        dry_run: bool,
+    ) -> anyhow::Result<Output> {
+        let mut cmd = Command::new("patch");
+        cmd.current_dir(self.settings.extracted_source_path_for_recipe(recipe))
+            .arg(format!("-p{}", patch.strip))
+            .args(["--forward", "--batch", "--no-backup-if-mismatch", "--input"])
+            .arg(file);
+
+        if dry_run {
+            cmd.arg("--dry-run");
+        }
+
+        Ok(cmd.output().await?)
+    }
+}
+
+/// Collects the hunks `patch` rejected from its output, e.g. `hunk #2 of src/main.c at 45`.
+///
+/// Falls back to the last line of output when no hunk is named, like when a file to patch is
+/// missing entirely.
+fn rejected_hunks(output: &Output) -> Vec<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    let mut current_file = None;
+    let mut rejected = vec![];
+
+    for line in stdout.lines().chain(stderr.lines()) {
+        if let Some(file) = line
+            .strip_prefix("patching file ")
+            .or_else(|| line.strip_prefix("checking file "))
+        {
+            current_file = Some(file.trim_matches(['\'', '"']).to_string());
+            continue;
+        }
+
+        if line.starts_with("Reversed (or previously applied) patch detected") {
+            rejected.push(format!(
+                "{} is already patched",
+                current_file.as_deref().unwrap_or("file")
+            ));
+            continue;
+        }
+
+        let hunk = line
+            .strip_prefix("Hunk #")
+            .and_then(|rest| rest.split_once(" FAILED at "));
+
+        if let Some((number, at)) = hunk {
+            rejected.push(format!(
+                "hunk #{} of {} at line {}",
+                number,
+                current_file.as_deref().unwrap_or("unknown file"),
+                at.trim_end_matches('.')
+            ));
+        }
+    }
+
+    if rejected.is_empty() {
+        let last = stderr
+            .lines()
+            .chain(stdout.lines())
+            .rfind(|x| !x.trim().is_empty())
+            .unwrap_or("patch failed without output");
+
+        rejected.push(last.trim().to_string());
+    }
+
+    rejected
+}
@@ -8,7 +8,9 @@ use crate::Recipe;
 use anyhow::bail;
 use futures::future::BoxFuture;
 use futures::FutureExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::process::Command;
 
 #[derive(Debug)]
 pub struct Player {
@@ -116,7 +118,7 @@ impl Player {
         let mut pb_iter = playbook.iter();
 
         while let Some(action) = pb_iter.next() {
-            println!("    action {:?}", action);
+            println!("[{}]     action {:?}", state.recipe.name, action);
             match action {
                 Action::Default => {
                     found_default = true;
@@ -133,7 +135,7 @@ impl Player {
             default(&mut *state, context).await?;
 
             while let Some(action) = pb_iter.next() {
-                println!("    action {:?}", action);
+                println!("[{}]     action {:?}", state.recipe.name, action);
                 match action {
                     Action::Default => {
                         bail!(".default called twice");
@@ -171,12 +173,8 @@ impl Player {
                     cc_args.push(arg);
                 }
 
-                let mut cmd = environment.command(&state.recipe, "gcc", &cc_args);
-                let mut proc = cmd.spawn()?;
-                let ec = proc.wait().await?;
-                if !ec.success() {
-                    bail!("cc failed");
-                }
+                let cmd = environment.command(&state.recipe, "gcc", &cc_args);
+                run_command(cmd, "cc").await?;
             }
             Action::Make => {
                 let make_cmd = state
@@ -188,7 +186,7 @@ impl Player {
                     .map(|x| x.as_str())
                     .unwrap_or("make");
 
-                let jobs = format!("-j{}", environment.cpus + 1);
+                let jobs = format!("-j{}", state.cpus + 1);
                 let mut make_args: Vec<&str> = vec![&jobs];
 
                 if let Some(args) = state.recipe.style.vars.make_args.as_ref() {
@@ -197,12 +195,8 @@ impl Player {
                     }
                 }
 
-                let mut cmd = environment.command(&state.recipe, make_cmd, &make_args);
-                let mut proc = cmd.spawn()?;
-                let ec = proc.wait().await?;
-                if !ec.success() {
-                    bail!("make failed");
-                }
+                let cmd = environment.command(&state.recipe, make_cmd, &make_args);
+                run_command(cmd, "make").await?;
             }
             Action::MakeInstall => {
                 let make_cmd = state
@@ -229,12 +223,182 @@ impl Player {
                 make_args.push(&dest_dir);
                 make_args.push("install");
 
-                let mut cmd = environment.command(&state.recipe, make_cmd, &make_args);
-                let mut proc = cmd.spawn()?;
-                let ec = proc.wait().await?;
-                if !ec.success() {
-                    bail!("make install failed");
+                let cmd = environment.command(&state.recipe, make_cmd, &make_args);
+                run_command(cmd, "make install").await?;
+            }
+            Action::CmakeConfigure => {
+                let vars = &state.recipe.style.vars;
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_dir = build_dir.to_string_lossy();
+                let build_type = format!(
+                    "-DCMAKE_BUILD_TYPE={}",
+                    vars.cmake_build_type.as_deref().unwrap_or("Release")
+                );
+
+                let mut cmake_args: Vec<&str> = vec![
+                    "-S",
+                    ".",
+                    "-B",
+                    &build_dir,
+                    "-DCMAKE_INSTALL_PREFIX=/usr",
+                    &build_type,
+                ];
+
+                if let Some(generator) = &vars.cmake_generator {
+                    cmake_args.push("-G");
+                    cmake_args.push(generator);
                 }
+
+                let c_flags = vars
+                    .cc_flags
+                    .as_ref()
+                    .map(|flags| format!("-DCMAKE_C_FLAGS={}", flags.join(" ")));
+                let cxx_flags = vars
+                    .cxx_flags
+                    .as_ref()
+                    .map(|flags| format!("-DCMAKE_CXX_FLAGS={}", flags.join(" ")));
+                cmake_args.extend(c_flags.as_deref());
+                cmake_args.extend(cxx_flags.as_deref());
+
+                if let Some(args) = vars.cmake_args.as_ref() {
+                    for arg in args {
+                        cmake_args.push(arg);
+                    }
+                }
+
+                let cmd = environment.command(state.recipe, "cmake", &cmake_args);
+                run_command(cmd, "cmake").await?;
+            }
+            Action::CmakeBuild => {
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_dir = build_dir.to_string_lossy();
+                let jobs = (state.cpus + 1).to_string();
+
+                let cmd = environment.command(
+                    state.recipe,
+                    "cmake",
+                    &["--build", &build_dir, "--parallel", &jobs],
+                );
+                run_command(cmd, "cmake --build").await?;
+            }
+            Action::CmakeInstall => {
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_dir = build_dir.to_string_lossy();
+
+                let mut cmd =
+                    environment.command(state.recipe, "cmake", &["--install", &build_dir]);
+                cmd.env("DESTDIR", self.settings.dest_path_for_recipe(state.recipe));
+                run_command(cmd, "cmake --install").await?;
+            }
+            Action::CmakeCheck => {
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_dir = build_dir.to_string_lossy();
+                let jobs = (state.cpus + 1).to_string();
+
+                let cmd = environment.command(
+                    state.recipe,
+                    "ctest",
+                    &[
+                        "--test-dir",
+                        &build_dir,
+                        "--output-on-failure",
+                        "--parallel",
+                        &jobs,
+                    ],
+                );
+                run_command(cmd, "ctest").await?;
+            }
+            Action::MesonSetup => {
+                let vars = &state.recipe.style.vars;
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_type = format!(
+                    "--buildtype={}",
+                    vars.meson_build_type.as_deref().unwrap_or("release")
+                );
+
+                // subprojects have to be vendored as artifacts, builds never download them
+                let mut meson_args: Vec<String> = vec![
+                    "setup".into(),
+                    "--prefix=/usr".into(),
+                    build_type,
+                    "--wrap-mode=nodownload".into(),
+                ];
+
+                // setting up an existing build directory again fails without this
+                if tokio::fs::metadata(build_dir.join("meson-private"))
+                    .await
+                    .is_ok()
+                {
+                    meson_args.push("--reconfigure".into());
+                }
+
+                for cross_file in vars.meson_cross_files.iter().flatten() {
+                    let path = match state.recipe.directory() {
+                        Some(dir) if Path::new(cross_file).is_relative() => dir.join(cross_file),
+                        _ => PathBuf::from(cross_file),
+                    };
+
+                    meson_args.push("--cross-file".into());
+                    meson_args.push(path.to_string_lossy().into_owned());
+                }
+
+                meson_args.extend(vars.meson_args.iter().flatten().cloned());
+                meson_args.push(build_dir.to_string_lossy().into_owned());
+                meson_args.push(".".into());
+
+                let meson_args: Vec<&str> = meson_args.iter().map(String::as_str).collect();
+                let mut cmd = environment.command(state.recipe, "meson", &meson_args);
+                if let Some(flags) = &vars.cc_flags {
+                    cmd.env("CFLAGS", flags.join(" "));
+                }
+                if let Some(flags) = &vars.cxx_flags {
+                    cmd.env("CXXFLAGS", flags.join(" "));
+                }
+
+                run_command(cmd, "meson setup").await?;
+            }
+            Action::MesonCompile => {
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_dir = build_dir.to_string_lossy();
+                let jobs = (state.cpus + 1).to_string();
+
+                let cmd = environment.command(
+                    state.recipe,
+                    "meson",
+                    &["compile", "-C", &build_dir, "-j", &jobs],
+                );
+                run_command(cmd, "meson compile").await?;
+            }
+            Action::MesonInstall => {
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_dir = build_dir.to_string_lossy();
+
+                let mut cmd = environment.command(
+                    state.recipe,
+                    "meson",
+                    &["install", "-C", &build_dir, "--no-rebuild"],
+                );
+                cmd.env("DESTDIR", self.settings.dest_path_for_recipe(state.recipe));
+                run_command(cmd, "meson install").await?;
+            }
+            Action::MesonTest => {
+                let build_dir = self.settings.build_path_for_recipe(state.recipe);
+                let build_dir = build_dir.to_string_lossy();
+                let jobs = (state.cpus + 1).to_string();
+
+                let cmd = environment.command(
+                    state.recipe,
+                    "meson",
+                    &[
+                        "test",
+                        "-C",
+                        &build_dir,
+                        "--print-errorlogs",
+                        "--num-processes",
+                        &jobs,
+                    ],
+                );
+                run_command(cmd, "meson test").await?;
             }
             Action::Bin(bin) => match context {
                 Context::Recipe(_) => {
@@ -322,15 +486,20 @@ impl Player {
                     }
                 }
 
-                let mut cmd = environment.command(&state.recipe, configure_script, &configure_args);
-                let mut proc = cmd.spawn()?;
-                let ec = proc.wait().await?;
-                if !ec.success() {
-                    bail!("configure failed");
-                }
+                let cmd = environment.command(&state.recipe, configure_script, &configure_args);
+                run_command(cmd, "configure").await?;
             }
         }
 
         Ok(())
     }
 }
+
+async fn run_command(mut cmd: Command, name: &str) -> anyhow::Result<()> {
+    let ec = cmd.spawn()?.wait().await?;
+    if !ec.success() {
+        bail!("{} failed", name);
+    }
+
+    Ok(())
+}
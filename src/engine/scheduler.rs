@@ -0,0 +1,97 @@
+use crate::definition::actions::Stage;
+use crate::definition::graph::RecipeGraph;
+use crate::engine::{Engine, EngineError};
+use crate::Recipe;
+use anyhow::{bail, Context};
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+
+impl Engine {
+    /// Runs the given stages for all recipes, building up to `jobs` recipes at the same time.
+    ///
+    /// A recipe is only started once every recipe it depends on has finished, the available
+    /// cpus are divided between the recipes that are being built at the same time.
+    pub async fn build_recipes(&self, recipes: &[Recipe], stages: &[Stage]) -> anyhow::Result<()> {
+        let graph = RecipeGraph::new(recipes);
+        graph.sorted_from(0..recipes.len())?;
+
+        if self.settings.offline && stages.contains(&Stage::Fetch) {
+            if let Err(e) = self.check_offline_cache(recipes).await {
+                eprintln!("{:?}", miette::Error::new(e));
+                bail!("can't build offline");
+            }
+        }
+
+        let mut waiting_on = vec![0; recipes.len()];
+        let mut dependents = vec![vec![]; recipes.len()];
+        for (idx, waiting) in waiting_on.iter_mut().enumerate() {
+            for dep in graph.dependencies(idx) {
+                dependents[*dep].push(idx);
+                *waiting += 1;
+            }
+        }
+
+        let mut ready: VecDeque<usize> = waiting_on
+            .iter()
+            .enumerate()
+            .filter(|(_, waiting)| **waiting == 0)
+            .map(|(idx, _)| idx)
+            .collect();
+
+        let jobs = self.settings.jobs;
+        let total_cpus = self.environment.cpus;
+        let build = |idx: usize, cpus: usize| async move {
+            let recipe = &recipes[idx];
+            let res = self
+                .run_stages(recipe, stages, cpus)
+                .await
+                .with_context(|| format!("failed to build {}", recipe.name));
+
+            (idx, res)
+        };
+
+        let mut running = FuturesUnordered::new();
+        let mut errors = vec![];
+
+        loop {
+            while errors.is_empty() && running.len() < jobs {
+                let idx = if let Some(idx) = ready.pop_front() {
+                    idx
+                } else {
+                    break;
+                };
+
+                let concurrent = min(jobs, running.len() + ready.len() + 1);
+                let cpus = max(1, total_cpus / concurrent);
+                println!("[{}] starting build with {} cpus", recipes[idx].name, cpus);
+                running.push(build(idx, cpus));
+            }
+
+            let (idx, res) = if let Some(done) = running.next().await {
+                done
+            } else {
+                break;
+            };
+
+            if let Err(e) = res {
+                errors.push(e);
+                continue;
+            }
+
+            for dependent in &dependents[idx] {
+                waiting_on[*dependent] -= 1;
+                if waiting_on[*dependent] == 0 {
+                    ready.push_back(*dependent);
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(EngineError { errors }.into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use crate::definition::SignatureKind;
+use blake2::{Blake2b512, Digest};
+use ring::signature::{UnparsedPublicKey, ED25519};
+use std::path::Path;
+use tokio::process::Command;
+
+const ALG_ED25519: &[u8; 2] = b"Ed";
+const ALG_ED25519_PREHASHED: &[u8; 2] = b"ED";
+
+/// Checks the detached signature at `signature` for `file` against `key`.
+///
+/// The outer result fails when the check couldn't be done at all, the inner one when the
+/// signature didn't verify, with the reason why.
+pub async fn verify_signature(
+    kind: SignatureKind,
+    key: &Path,
+    signature: &Path,
+    file: &Path,
+) -> anyhow::Result<Result<(), String>> {
+    match kind {
+        SignatureKind::OpenPgp => verify_openpgp(key, signature, file).await,
+        SignatureKind::Minisign | SignatureKind::Signify => {
+            let key = tokio::fs::read_to_string(key).await?;
+            let signature = tokio::fs::read_to_string(signature).await?;
+            let data = tokio::fs::read(file).await?;
+
+            Ok(verify_ed25519(kind, &key, &signature, &data))
+        }
+    }
+}
+
+async fn verify_openpgp(
+    key: &Path,
+    signature: &Path,
+    file: &Path,
+) -> anyhow::Result<Result<(), String>> {
+    let output = Command::new("gpgv")
+        .arg("--keyring")
+        .arg(key)
+        .arg(signature)
+        .arg(file)
+        .output()
+        .await?;
+
+    if output.status.success() {
+        Ok(Ok(()))
+    } else {
+        Ok(Err(String::from_utf8_lossy(&output.stderr)
+            .trim()
+            .to_string()))
+    }
+}
+
+/// Verifies minisign and signify signatures, which share the same key and signature layout:
+/// a 2 byte algorithm, an 8 byte key id and the ed25519 key or signature.
+///
+/// Minisign additionally signs the trusted comment, and may sign a blake2b hash of the file
+/// instead of the file itself.
+fn verify_ed25519(
+    kind: SignatureKind,
+    key: &str,
+    signature: &str,
+    data: &[u8],
+) -> Result<(), String> {
+    let key = decode_line(key, 0).ok_or("public key is malformed")?;
+    if key.len() != 42 || &key[..2] != ALG_ED25519 {
+        return Err("public key is not an ed25519 key".to_string());
+    }
+
+    let sig = decode_line(signature, 0).ok_or("signature is malformed")?;
+    if sig.len() != 74 {
+        return Err("signature is malformed".to_string());
+    }
+
+    if key[2..10] != sig[2..10] {
+        return Err(format!(
+            "signature was made with key {} but key {} was given",
+            hex::encode(&sig[2..10]),
+            hex::encode(&key[2..10])
+        ));
+    }
+
+    let public_key = UnparsedPublicKey::new(&ED25519, &key[10..]);
+    let prehashed;
+    let message = match (&sig[..2], kind) {
+        (alg, _) if alg == ALG_ED25519 => data,
+        (alg, SignatureKind::Minisign) if alg == ALG_ED25519_PREHASHED => {
+            prehashed = Blake2b512::digest(data);
+            prehashed.as_slice()
+        }
+        _ => return Err("signature uses an unsupported algorithm".to_string()),
+    };
+
+    public_key
+        .verify(message, &sig[10..])
+        .map_err(|_| "signature does not match".to_string())?;
+
+    if kind == SignatureKind::Minisign {
+        let trusted_comment = signature
+            .lines()
+            .find_map(|x| x.strip_prefix("trusted comment: "))
+            .ok_or("signature is missing its trusted comment")?;
+        let global =
+            decode_line(signature, 2).ok_or("signature is missing its global signature")?;
+
+        let mut signed = sig[10..].to_vec();
+        signed.extend_from_slice(trusted_comment.as_bytes());
+
+        public_key
+            .verify(&signed, &global)
+            .map_err(|_| "trusted comment of signature does not match".to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Decodes the base64 line following the `untrusted comment:` line, skipping `skip` lines after
+/// it.
+fn decode_line(data: &str, skip: usize) -> Option<Vec<u8>> {
+    let line = data
+        .lines()
+        .skip_while(|x| x.starts_with("untrusted comment:"))
+        .nth(skip)?;
+
+    base64::decode(line.trim()).ok()
+}